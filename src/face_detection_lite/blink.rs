@@ -0,0 +1,271 @@
+use crate::face_detection_lite::iris_landmark::IrisResults;
+use crate::face_detection_lite::types::Landmark;
+use anyhow::Error;
+
+/// Number of face landmarks (from face landmark results)
+const NUM_FACE_LANDMARKS: usize = 468;
+
+/// Eye contour indices (from iris landmark results) of the eye corners
+const EYE_CONTOUR_CORNERS: (usize, usize) = (0, 8);
+/// Eye contour indices of vertically opposite upper and lower eyelid points
+const EYE_CONTOUR_LIDS: [(usize, usize); 5] = [(10, 2), (11, 3), (12, 4), (13, 5), (14, 6)];
+
+/// Face landmark indices of the left eye corners and eyelid pairs
+const LEFT_EYE_CORNERS: (usize, usize) = (33, 133);
+const LEFT_EYE_LIDS: [(usize, usize); 3] = [(160, 144), (159, 145), (158, 153)];
+/// Face landmark indices of the right eye corners and eyelid pairs
+const RIGHT_EYE_CORNERS: (usize, usize) = (362, 263);
+const RIGHT_EYE_LIDS: [(usize, usize); 3] = [(385, 380), (386, 374), (387, 373)];
+
+/// Calculate the eye aspect ratio (EAR): the mean distance between the
+/// upper and lower eyelid divided by the distance between the eye corners.
+fn aspect_ratio(
+    landmarks: &[Landmark], corners: (usize, usize), lids: &[(usize, usize)], image_size: (i32, i32),
+) -> f64 {
+    let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+    let distance = |a: usize, b: usize| -> f64 {
+        let (a, b) = (landmarks[a], landmarks[b]);
+        ((a.x - b.x) * width).hypot((a.y - b.y) * height)
+    };
+
+    let eye_width = distance(corners.0, corners.1);
+    if eye_width <= f64::EPSILON {
+        return 0.0;
+    }
+    let eye_height = lids.iter().map(|&(upper, lower)| distance(upper, lower)).sum::<f64>() / lids.len() as f64;
+    eye_height / eye_width
+}
+
+/// Calculate the eye aspect ratio from iris landmark detection results.
+/// * Args:
+///     - iris_results (`&IrisResults`): Result of an `IrisLandmark` call.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///
+/// * Returns:
+///     - (`f64`) Eye aspect ratio; roughly 0.25-0.35 for open and below 0.15 for closed eyes.
+pub fn iris_eye_aspect_ratio(iris_results: &IrisResults, image_size: (i32, i32)) -> f64 {
+    aspect_ratio(&iris_results.eyeball_contour(), EYE_CONTOUR_CORNERS, &EYE_CONTOUR_LIDS, image_size)
+}
+
+/// Calculate the eye aspect ratios of both eyes from face landmarks.
+/// * Args:
+///     - face_landmarks (`&[Landmark]`): Result of a `FaceLandmark` call.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///
+/// * Returns:
+///     - (`(f64, f64)`) Eye aspect ratios of the left and right eye.
+pub fn face_eye_aspect_ratios(face_landmarks: &[Landmark], image_size: (i32, i32)) -> Result<(f64, f64), Error> {
+    if face_landmarks.len() < NUM_FACE_LANDMARKS {
+        return Err(Error::msg("unexpected number of items in face_landmarks"));
+    }
+    let left = aspect_ratio(face_landmarks, LEFT_EYE_CORNERS, &LEFT_EYE_LIDS, image_size);
+    let right = aspect_ratio(face_landmarks, RIGHT_EYE_CORNERS, &RIGHT_EYE_LIDS, image_size);
+    Ok((left, right))
+}
+
+/// Options of the `BlinkDetector`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlinkDetectorOptions {
+    /// Eye aspect ratio below which an open eye is considered closed.
+    pub close_threshold: f64,
+    /// Eye aspect ratio above which a closed eye is considered open again.
+    /// Must be greater than `close_threshold` (hysteresis).
+    pub open_threshold: f64,
+    /// Eye aspect ratio of a fully closed eye (openness 0).
+    pub closed_aspect_ratio: f64,
+    /// Eye aspect ratio of a fully open eye (openness 1).
+    pub open_aspect_ratio: f64,
+    /// Minimum number of closed frames to count a blink.
+    pub min_closed_frames: u32,
+    /// Maximum duration of a blink in seconds; longer closures are not counted.
+    pub max_blink_duration: f64,
+}
+
+impl Default for BlinkDetectorOptions {
+    fn default() -> Self {
+        Self {
+            close_threshold: 0.18,
+            open_threshold: 0.22,
+            closed_aspect_ratio: 0.1,
+            open_aspect_ratio: 0.3,
+            min_closed_frames: 1,
+            max_blink_duration: 1.0,
+        }
+    }
+}
+
+/// A completed blink.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlinkEvent {
+    /// Timestamp of the first closed frame in seconds.
+    pub start: f64,
+    /// Timestamp of the first frame the eye was open again in seconds.
+    pub end: f64,
+    /// Number of frames the eye was closed.
+    pub num_frames: u32,
+}
+
+impl BlinkEvent {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// Detect blinks from a sequence of eye aspect ratios.
+///
+/// An eye closes when the aspect ratio drops below `close_threshold` and
+/// opens again once it rises above `open_threshold`; the gap between the two
+/// thresholds suppresses jitter around a single threshold.
+#[derive(Debug, Clone)]
+pub struct BlinkDetector {
+    options: BlinkDetectorOptions,
+    closed_since: Option<(f64, u32)>,
+    blinks: Vec<BlinkEvent>,
+    first_timestamp: Option<f64>,
+    last_timestamp: Option<f64>,
+}
+
+impl Default for BlinkDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlinkDetector {
+    pub fn new() -> Self {
+        Self {
+            options: BlinkDetectorOptions::default(),
+            closed_since: None,
+            blinks: Vec::new(),
+            first_timestamp: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Replace the default detector options.
+    pub fn with_options(mut self, options: BlinkDetectorOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> &BlinkDetectorOptions {
+        &self.options
+    }
+
+    /// Map an eye aspect ratio to an openness score [0, 1].
+    pub fn openness(&self, aspect_ratio: f64) -> f64 {
+        let range = self.options.open_aspect_ratio - self.options.closed_aspect_ratio;
+        if range <= 0.0 {
+            return 0.0;
+        }
+        ((aspect_ratio - self.options.closed_aspect_ratio) / range).clamp(0.0, 1.0)
+    }
+
+    /// `true` while the eye is considered closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed_since.is_some()
+    }
+
+    /// Add the eye aspect ratio of the next frame.
+    /// Use the mean of both eyes to detect blinks of the face.
+    /// * Args:
+    ///     - aspect_ratio (`f64`): Eye aspect ratio of the frame.
+    ///     - timestamp (`f64`): Timestamp of the frame in seconds.
+    ///
+    /// * Returns:
+    ///     - (`Option<BlinkEvent>`) The blink completed in this frame, if any.
+    pub fn update(&mut self, aspect_ratio: f64, timestamp: f64) -> Option<BlinkEvent> {
+        self.first_timestamp.get_or_insert(timestamp);
+        self.last_timestamp = Some(timestamp);
+
+        match self.closed_since {
+            None => {
+                if aspect_ratio < self.options.close_threshold {
+                    self.closed_since = Some((timestamp, 1));
+                }
+                None
+            }
+            Some((start, num_frames)) => {
+                if aspect_ratio <= self.options.open_threshold {
+                    self.closed_since = Some((start, num_frames + 1));
+                    return None;
+                }
+
+                self.closed_since = None;
+                let blink = BlinkEvent {
+                    start,
+                    end: timestamp,
+                    num_frames,
+                };
+                if num_frames < self.options.min_closed_frames || blink.duration() > self.options.max_blink_duration {
+                    return None;
+                }
+                self.blinks.push(blink);
+                Some(blink)
+            }
+        }
+    }
+
+    /// All blinks detected so far.
+    pub fn blinks(&self) -> &[BlinkEvent] {
+        &self.blinks
+    }
+
+    pub fn blink_count(&self) -> usize {
+        self.blinks.len()
+    }
+
+    /// Number of blinks per minute over the observed time span.
+    pub fn blink_rate(&self) -> Option<f64> {
+        let elapsed = self.last_timestamp? - self.first_timestamp?;
+        if elapsed <= 0.0 {
+            return None;
+        }
+        Some(self.blinks.len() as f64 * 60.0 / elapsed)
+    }
+
+    /// Mean blink duration in seconds.
+    pub fn mean_blink_duration(&self) -> Option<f64> {
+        if self.blinks.is_empty() {
+            return None;
+        }
+        Some(self.blinks.iter().map(|blink| blink.duration()).sum::<f64>() / self.blinks.len() as f64)
+    }
+
+    /// Forget all blinks and the current eye state.
+    pub fn reset(&mut self) {
+        self.closed_since = None;
+        self.blinks.clear();
+        self.first_timestamp = None;
+        self.last_timestamp = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blink_detector() {
+        let mut detector = BlinkDetector::new();
+        let aspect_ratios = [
+            0.30, 0.29, 0.10, 0.08, 0.20, 0.21, 0.28, 0.30, // blink with jitter between the thresholds
+            0.19, 0.17, 0.20, 0.30, // short blink
+            0.31, 0.30, 0.29, 0.30,
+        ];
+
+        let mut events = Vec::new();
+        for (n, &aspect_ratio) in aspect_ratios.iter().enumerate() {
+            if let Some(blink) = detector.update(aspect_ratio, n as f64 / 4.0) {
+                events.push(blink);
+            }
+        }
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].num_frames, 4);
+        assert_eq!(events[1].num_frames, 2);
+        assert_eq!(detector.blink_rate(), Some(2.0 * 60.0 / 3.75));
+        assert_eq!(detector.openness(0.3), 1.0);
+        assert_eq!(detector.openness(0.05), 0.0);
+    }
+}
@@ -0,0 +1,13 @@
+//! TFLite models shipped in `models/`, embedded into the binary with the
+//! `embedded-models` feature.
+//!
+//! The face embeddings model is not distributed with the crate and therefore
+//! cannot be embedded; use `FaceEmbeddings::from_buffer` instead.
+
+pub const FACE_DETECTION_FRONT: &[u8] = include_bytes!("../../models/face_detection_front.tflite");
+pub const FACE_DETECTION_BACK: &[u8] = include_bytes!("../../models/face_detection_back.tflite");
+pub const FACE_DETECTION_SHORT: &[u8] = include_bytes!("../../models/face_detection_short_range.tflite");
+pub const FACE_DETECTION_FULL: &[u8] = include_bytes!("../../models/face_detection_full_range.tflite");
+pub const FACE_DETECTION_FULL_SPARSE: &[u8] = include_bytes!("../../models/face_detection_full_range_sparse.tflite");
+pub const FACE_LANDMARK: &[u8] = include_bytes!("../../models/face_landmark.tflite");
+pub const IRIS_LANDMARK: &[u8] = include_bytes!("../../models/iris_landmark.tflite");
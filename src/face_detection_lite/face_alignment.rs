@@ -0,0 +1,267 @@
+use crate::face_detection_lite::face_detection::FaceIndex;
+use crate::face_detection_lite::types::{Detection, Landmark, Rect};
+use anyhow::Error;
+use nalgebra::{Matrix2, Vector2};
+#[cfg(feature = "opencv")]
+use opencv::core::{Mat, MatTraitConst, Scalar, Size, BORDER_CONSTANT};
+#[cfg(feature = "opencv")]
+use opencv::imgproc::{warp_affine, INTER_LINEAR};
+
+/// Size of the ArcFace reference template in pixels
+pub const TEMPLATE_SIZE: i32 = 112;
+
+/// Canonical 5-point ArcFace template for a 112x112 face chip:
+/// left eye, right eye, nose tip, left mouth corner and right mouth corner
+/// (left and right as seen in the image).
+pub const ARCFACE_TEMPLATE: [(f64, f64); 5] = [
+    (38.2946, 51.6963),
+    (73.5318, 51.5014),
+    (56.0252, 71.7366),
+    (41.5493, 92.3655),
+    (70.7299, 92.2041),
+];
+
+/// Face mesh landmarks of the eye corners, nose tip and mouth corners
+const MESH_LEFT_EYE: [usize; 2] = [33, 133];
+const MESH_RIGHT_EYE: [usize; 2] = [362, 263];
+const MESH_NOSE_TIP: usize = 1;
+const MESH_MOUTH_LEFT: usize = 61;
+const MESH_MOUTH_RIGHT: usize = 291;
+const NUM_FACE_LANDMARKS: usize = 468;
+
+/// Source of the keypoints used to align a face.
+#[derive(Debug, Clone)]
+pub enum FaceKeypoints {
+    /// BlazeFace detection; uses both eyes, the nose tip and the mouth center.
+    Detection(Detection),
+    /// 468 face mesh landmarks; uses both eye centers, the nose tip and the mouth corners.
+    Landmarks(Vec<Landmark>),
+}
+
+impl From<Detection> for FaceKeypoints {
+    fn from(detection: Detection) -> Self {
+        FaceKeypoints::Detection(detection)
+    }
+}
+
+impl From<Vec<Landmark>> for FaceKeypoints {
+    fn from(landmarks: Vec<Landmark>) -> Self {
+        FaceKeypoints::Landmarks(landmarks)
+    }
+}
+
+impl FaceKeypoints {
+    /// Return pairs of absolute image points and matching template points.
+    /// * Args:
+    ///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+    ///     - output_size (`i32`): Size of the aligned face chip in pixels.
+    pub fn correspondences(
+        &self, image_size: (i32, i32), output_size: i32,
+    ) -> Result<(Vec<(f64, f64)>, Vec<(f64, f64)>), Error> {
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        let scale = output_size as f64 / TEMPLATE_SIZE as f64;
+        let template: Vec<(f64, f64)> = ARCFACE_TEMPLATE
+            .iter()
+            .map(|&(x, y)| (x * scale, y * scale))
+            .collect();
+
+        match self {
+            FaceKeypoints::Detection(detection) => {
+                if detection.keypoint_count() <= FaceIndex::Mouth as usize {
+                    return Err(Error::msg("detection is missing face keypoints"));
+                }
+                let keypoint = |index: FaceIndex| {
+                    let (x, y) = detection.keypoint(index as usize);
+                    (x as f64 * width, y as f64 * height)
+                };
+                let src = vec![
+                    keypoint(FaceIndex::LeftEye),
+                    keypoint(FaceIndex::RightEye),
+                    keypoint(FaceIndex::NoseTip),
+                    keypoint(FaceIndex::Mouth),
+                ];
+                let mouth_center = ((template[3].0 + template[4].0) / 2., (template[3].1 + template[4].1) / 2.);
+                let dst = vec![template[0], template[1], template[2], mouth_center];
+                Ok((src, dst))
+            }
+            FaceKeypoints::Landmarks(landmarks) => {
+                if landmarks.len() < NUM_FACE_LANDMARKS {
+                    return Err(Error::msg("unexpected number of items in face landmarks"));
+                }
+                let point = |indices: &[usize]| {
+                    let n = indices.len() as f64;
+                    let x = indices.iter().map(|&i| landmarks[i].x).sum::<f64>() / n;
+                    let y = indices.iter().map(|&i| landmarks[i].y).sum::<f64>() / n;
+                    (x * width, y * height)
+                };
+                let src = vec![
+                    point(&MESH_LEFT_EYE),
+                    point(&MESH_RIGHT_EYE),
+                    point(&[MESH_NOSE_TIP]),
+                    point(&[MESH_MOUTH_LEFT]),
+                    point(&[MESH_MOUTH_RIGHT]),
+                ];
+                Ok((src, template))
+            }
+        }
+    }
+}
+
+/// Estimate the similarity transform (rotation, uniform scale and translation)
+/// mapping `src` onto `dst` in the least squares sense.
+///
+/// Reference:
+///     S. Umeyama. Least-squares estimation of transformation parameters
+///     between two point patterns. IEEE TPAMI, 1991.
+///
+/// * Returns:
+///     - (`[[f64; 3]; 2]`) 2x3 affine matrix.
+pub fn similarity_transform(src: &[(f64, f64)], dst: &[(f64, f64)]) -> Result<[[f64; 3]; 2], Error> {
+    if src.len() != dst.len() || src.len() < 2 {
+        return Err(Error::msg("src and dst must contain the same number of points (at least 2)"));
+    }
+
+    let n = src.len() as f64;
+    let src: Vec<Vector2<f64>> = src.iter().map(|&(x, y)| Vector2::new(x, y)).collect();
+    let dst: Vec<Vector2<f64>> = dst.iter().map(|&(x, y)| Vector2::new(x, y)).collect();
+    let src_mean = src.iter().sum::<Vector2<f64>>() / n;
+    let dst_mean = dst.iter().sum::<Vector2<f64>>() / n;
+
+    let mut covariance = Matrix2::<f64>::zeros();
+    let mut src_variance = 0.;
+    for (s, d) in src.iter().zip(dst.iter()) {
+        let (s, d) = (s - src_mean, d - dst_mean);
+        covariance += d * s.transpose();
+        src_variance += s.norm_squared();
+    }
+    covariance /= n;
+    src_variance /= n;
+
+    if src_variance <= f64::EPSILON {
+        return Err(Error::msg("src points must not coincide"));
+    }
+
+    let svd = covariance.svd(true, true);
+    let (u, v_t) = match (svd.u, svd.v_t) {
+        (Some(u), Some(v_t)) => (u, v_t),
+        _ => return Err(Error::msg("failed to decompose the covariance matrix")),
+    };
+
+    // Avoid reflections
+    let mut d = Vector2::new(1., 1.);
+    if covariance.determinant() < 0. {
+        d[1] = -1.;
+    }
+
+    let rotation = u * Matrix2::from_diagonal(&d) * v_t;
+    let scale = svd.singular_values.dot(&d) / src_variance;
+    let translation = dst_mean - scale * rotation * src_mean;
+
+    Ok([
+        [scale * rotation[(0, 0)], scale * rotation[(0, 1)], translation[0]],
+        [scale * rotation[(1, 0)], scale * rotation[(1, 1)], translation[1]],
+    ])
+}
+
+/// Return the (rotated) region of the image that is mapped onto the aligned face chip.
+/// The ROI can be passed to `ImageInput::to_tensor` to align a face without OpenCV.
+/// * Args:
+///     - face (`&FaceKeypoints`): Detection or face landmarks of the face to align.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///     - output_size (`i32`): Width and height of the aligned face chip.
+///
+/// * Returns:
+///    `Rect` - ROI in absolute pixel coordinates.
+pub fn aligned_face_roi(face: &FaceKeypoints, image_size: (i32, i32), output_size: i32) -> Result<Rect, Error> {
+    let (src, dst) = face.correspondences(image_size, output_size)?;
+    let matrix = similarity_transform(&src, &dst)?;
+
+    // matrix = [[a, -b, tx], [b, a, ty]] with a = s * cos(angle), b = s * sin(angle)
+    let (a, b) = (matrix[0][0], matrix[1][0]);
+    let scale = a.hypot(b);
+    if scale <= f64::EPSILON {
+        return Err(Error::msg("degenerate face alignment"));
+    }
+
+    // map the center of the face chip back onto the image
+    let half = output_size as f64 / 2.;
+    let (dx, dy) = (half - matrix[0][2], half - matrix[1][2]);
+    let x_center = (a * dx + b * dy) / scale.powi(2);
+    let y_center = (-b * dx + a * dy) / scale.powi(2);
+    let size = output_size as f64 / scale;
+
+    Ok(Rect::new(x_center, y_center, size, size, (-b).atan2(a), false))
+}
+
+/// Warp a face to the canonical ArcFace template.
+/// * Args:
+///     - image (`&Mat`): Input OpenCV matrix.
+///     - face (`&FaceKeypoints`): Detection or face landmarks of the face to align.
+///     - output_size (`i32`): Width and height of the aligned face chip; ArcFace models use 112.
+///
+/// * Returns:
+///    `Mat` - Aligned face chip.
+#[cfg(feature = "opencv")]
+pub fn align_face(image: &Mat, face: &FaceKeypoints, output_size: i32) -> Result<Mat, Error> {
+    let img_shape = image.size()?;
+    let (src, dst) = face.correspondences((img_shape.width, img_shape.height), output_size)?;
+    let matrix = similarity_transform(&src, &dst)?;
+    let matrix = Mat::from_slice_2d(&matrix)?;
+
+    let mut aligned = Mat::default();
+    warp_affine(
+        image,
+        &mut aligned,
+        &matrix,
+        Size::new(output_size, output_size),
+        INTER_LINEAR,
+        BORDER_CONSTANT,
+        Scalar::all(0.0),
+    )?;
+    Ok(aligned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similarity_transform() {
+        let (angle, scale, tx, ty) = (0.3_f64, 1.7, 12.0, -4.0);
+        let transform = |(x, y): (f64, f64)| {
+            (
+                scale * (angle.cos() * x - angle.sin() * y) + tx,
+                scale * (angle.sin() * x + angle.cos() * y) + ty,
+            )
+        };
+        let src = ARCFACE_TEMPLATE.to_vec();
+        let dst: Vec<(f64, f64)> = src.iter().map(|&p| transform(p)).collect();
+
+        let matrix = similarity_transform(&src, &dst).unwrap();
+        for (s, d) in src.iter().zip(dst.iter()) {
+            let x = matrix[0][0] * s.0 + matrix[0][1] * s.1 + matrix[0][2];
+            let y = matrix[1][0] * s.0 + matrix[1][1] * s.1 + matrix[1][2];
+            assert!((x - d.0).abs() < 1e-6 && (y - d.1).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_aligned_face_roi() {
+        let keypoints = vec![0.3, 0.3, 0.7, 0.7, 0.42, 0.45, 0.6, 0.42, 0.52, 0.55, 0.53, 0.65];
+        let face = FaceKeypoints::from(Detection::new(keypoints, 0.9));
+        let image_size = (640, 480);
+
+        let roi = aligned_face_roi(&face, image_size, TEMPLATE_SIZE).unwrap();
+        let (src, dst) = face.correspondences(image_size, TEMPLATE_SIZE).unwrap();
+        let matrix = similarity_transform(&src, &dst).unwrap();
+
+        // the ROI corners map onto the corners of the face chip
+        let size = TEMPLATE_SIZE as f64;
+        let corners = [(0., 0.), (size, 0.), (size, size), (0., size)];
+        for (point, corner) in roi.points().iter().zip(corners) {
+            let x = matrix[0][0] * point.0 + matrix[0][1] * point.1 + matrix[0][2];
+            let y = matrix[1][0] * point.0 + matrix[1][1] * point.1 + matrix[1][2];
+            assert!((x - corner.0).abs() < 1e-6 && (y - corner.1).abs() < 1e-6);
+        }
+    }
+}
@@ -1,15 +1,15 @@
+#[cfg(feature = "embedded-models")]
+use crate::face_detection_lite::embedded_models;
+use crate::face_detection_lite::input::ImageInput;
+use crate::face_detection_lite::interpreter::{batch_items, InterpreterPool, PooledInterpreter};
 use crate::face_detection_lite::nms::non_maximum_suppression;
-use crate::face_detection_lite::transform::{detection_letterbox_removal, image_to_tensor, sigmoid};
+use crate::face_detection_lite::transform::{detection_letterbox_removal, sigmoid};
 use crate::face_detection_lite::types::{Detection, Rect};
 use anyhow::Error;
 use ndarray::parallel::prelude::*;
 use ndarray::{s, Array, Array2, Array3, Axis};
-use opencv::core::Mat;
 use std::ops::{AddAssign, Div};
 use std::path::PathBuf;
-use tflite::op_resolver::OpResolver;
-use tflite::ops::builtin::BuiltinOpResolver;
-use tflite::{FlatBufferModel, InterpreterBuilder};
 
 /// BlazeFace face detection.
 ///
@@ -132,26 +132,163 @@ const MODEL_NAME_FULL_SPARSE: &str = "face_detection_full_range_sparse.tflite";
 /// this lower limit is safe for use with the sigmoid functions and float32
 const RAW_SCORE_LIMIT: f32 = 80.0;
 
-/// threshold for confidence scores
+/// default threshold for confidence scores
 const MIN_SCORE: f32 = 0.5;
 
-/// NMS similarity threshold
+/// default NMS similarity threshold
 const MIN_SUPPRESSION_THRESHOLD: f32 = 0.3;
 
+/// Post-processing options for `FaceDetection`.
+/// Use these to tune recall and precision of the detector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceDetectionOptions {
+    /// Minimum confidence score [0, 1] of a detection.
+    pub min_score: f32,
+    /// IoU above which overlapping detections are suppressed (or merged).
+    pub min_suppression_threshold: f32,
+    /// `true` merges overlapping detections weighted by their scores (MediaPipe default);
+    /// `false` keeps only the highest scoring detection (hard NMS).
+    pub weighted_nms: bool,
+    /// Maximum number of faces to return; `None` returns all faces.
+    pub max_num_faces: Option<usize>,
+    /// Minimum width and height of a face in pixels; smaller faces are discarded.
+    pub min_face_size: Option<f64>,
+}
+
+impl Default for FaceDetectionOptions {
+    fn default() -> Self {
+        Self {
+            min_score: MIN_SCORE,
+            min_suppression_threshold: MIN_SUPPRESSION_THRESHOLD,
+            weighted_nms: true,
+            max_num_faces: None,
+            min_face_size: None,
+        }
+    }
+}
+
+impl FaceDetectionOptions {
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    pub fn with_min_suppression_threshold(mut self, threshold: f32) -> Self {
+        self.min_suppression_threshold = threshold;
+        self
+    }
+
+    pub fn with_weighted_nms(mut self, weighted: bool) -> Self {
+        self.weighted_nms = weighted;
+        self
+    }
+
+    pub fn with_max_num_faces(mut self, max_num_faces: usize) -> Self {
+        self.max_num_faces = Some(max_num_faces);
+        self
+    }
+
+    pub fn with_min_face_size(mut self, min_face_size: f64) -> Self {
+        self.min_face_size = Some(min_face_size);
+        self
+    }
+}
+
+/// Face orientations searched by `FaceDetection::infer_rotated` to find
+/// faces in images of unknown orientation, in degrees clockwise.
+pub const RIGHT_ANGLE_ROTATIONS: [f64; 4] = [0., 90., 180., 270.];
+
+/// Image pyramid of overlapping tiles used by `FaceDetection::infer_tiled`.
+///
+/// Every pyramid level covers the image with square tiles whose edge length
+/// is a fraction (`scale`) of the shorter image side. Each tile is resized
+/// to the model input size, so smaller scales find smaller faces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TilingOptions {
+    /// Tile sizes relative to the shorter image side, one pyramid level each, in (0, 1].
+    pub scales: Vec<f64>,
+    /// Minimum overlap between neighbouring tiles as a fraction of the tile size, in [0, 1).
+    pub overlap: f64,
+}
+
+impl Default for TilingOptions {
+    fn default() -> Self {
+        Self {
+            scales: vec![1.0, 0.5],
+            overlap: 0.25,
+        }
+    }
+}
+
+impl TilingOptions {
+    pub fn with_scales(mut self, scales: Vec<f64>) -> Self {
+        self.scales = scales;
+        self
+    }
+
+    pub fn with_overlap(mut self, overlap: f64) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Return the normalized ROIs of all tiles of the pyramid for an image.
+    pub fn tiles(&self, image_size: (i32, i32)) -> Result<Vec<Rect>, Error> {
+        if self.scales.is_empty() || self.scales.iter().any(|&scale| scale <= 0.0 || scale > 1.0) {
+            return Err(Error::msg("tiling scales must be in (0, 1]"));
+        }
+        if !(0.0..1.0).contains(&self.overlap) {
+            return Err(Error::msg("tiling overlap must be in [0, 1)"));
+        }
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        if width <= 0.0 || height <= 0.0 {
+            return Err(Error::msg("image must not be empty"));
+        }
+
+        let mut tiles = Vec::new();
+        for &scale in &self.scales {
+            let tile = (width.min(height) * scale).max(1.0);
+            let stride = tile * (1.0 - self.overlap);
+            for y in tile_starts(height, tile, stride) {
+                for x in tile_starts(width, tile, stride) {
+                    tiles.push(Rect::new(
+                        (x + tile / 2.0) / width,
+                        (y + tile / 2.0) / height,
+                        tile / width,
+                        tile / height,
+                        0.0,
+                        true,
+                    ));
+                }
+            }
+        }
+        Ok(tiles)
+    }
+}
+
+/// Evenly spaced start positions of tiles covering `length` with at most `stride` between tiles.
+fn tile_starts(length: f64, tile: f64, stride: f64) -> Vec<f64> {
+    if tile >= length {
+        return vec![0.0];
+    }
+    let count = ((length - tile) / stride).ceil() as usize + 1;
+    let step = (length - tile) / (count - 1) as f64;
+    (0..count).map(|i| i as f64 * step).collect()
+}
+
 /// BlazeFace face detection model as used by Google MediaPipe.
 /// This model can detect multiple faces and returns a list of detections.
 /// Each detection contains the normalised [0,1] position and size of the
 /// detected face, as well as a number of keypoints (also normalised to
 /// [0,1]).
 pub struct FaceDetection {
-    model_path: PathBuf,
-    model: FlatBufferModel,
+    model_path: Option<PathBuf>,
+    interpreters: InterpreterPool,
     anchors: Array2<f32>,
+    options: FaceDetectionOptions,
 }
 
 impl FaceDetection {
     pub fn new(model_type: FaceDetectionModel, model_path: Option<String>) -> Result<FaceDetection, Error> {
-        let mut ssd_opts = SSDOptions::new_front(); // Placeholder for SSD options
         let mut model_path_buf: PathBuf;
 
         if let Some(path) = model_path {
@@ -160,78 +297,216 @@ impl FaceDetection {
             model_path_buf = PathBuf::from("./models");
         }
 
-        match model_type {
-            FaceDetectionModel::FrontCamera => {
-                model_path_buf.push(MODEL_NAME_FRONT);
-                ssd_opts = SSDOptions::new_front();
-            }
-            FaceDetectionModel::BackCamera => {
-                model_path_buf.push(MODEL_NAME_BACK);
-                ssd_opts = SSDOptions::new_back();
-            }
-            FaceDetectionModel::Short => {
-                model_path_buf.push(MODEL_NAME_SHORT);
-                ssd_opts = SSDOptions::new_short();
-            }
-            FaceDetectionModel::Full => {
-                model_path_buf.push(MODEL_NAME_FULL);
-                ssd_opts = SSDOptions::new_full();
-            }
-            FaceDetectionModel::FullSparse => {
-                model_path_buf.push(MODEL_NAME_FULL_SPARSE);
-                ssd_opts = SSDOptions::new_full();
-            }
-            _ => return Err(Error::msg("unsupported model type")),
-        }
+        let (model_name, ssd_opts) = model_spec(&model_type);
+        model_path_buf.push(model_name);
+
+        let interpreters = InterpreterPool::from_file(&model_path_buf, 1)?;
+        Self::from_model(interpreters, &ssd_opts, Some(model_path_buf))
+    }
+
+    /// Create the face detection from an in-memory TFLite model.
+    /// * Args:
+    ///     - model_type (`FaceDetectionModel`): Type of the model contained in the buffer.
+    ///     - buffer (`impl Into<Vec<u8>>`): Content of the `.tflite` model file.
+    pub fn from_buffer(model_type: FaceDetectionModel, buffer: impl Into<Vec<u8>>) -> Result<FaceDetection, Error> {
+        let (_, ssd_opts) = model_spec(&model_type);
+        let interpreters = InterpreterPool::new(buffer.into(), 1)?;
+        Self::from_model(interpreters, &ssd_opts, None)
+    }
+
+    /// Create the face detection from the model bundled with the crate.
+    #[cfg(feature = "embedded-models")]
+    pub fn from_embedded(model_type: FaceDetectionModel) -> Result<FaceDetection, Error> {
+        let buffer = match model_type {
+            FaceDetectionModel::FrontCamera => embedded_models::FACE_DETECTION_FRONT,
+            FaceDetectionModel::BackCamera => embedded_models::FACE_DETECTION_BACK,
+            FaceDetectionModel::Short => embedded_models::FACE_DETECTION_SHORT,
+            FaceDetectionModel::Full => embedded_models::FACE_DETECTION_FULL,
+            FaceDetectionModel::FullSparse => embedded_models::FACE_DETECTION_FULL_SPARSE,
+        };
+        Self::from_buffer(model_type, buffer)
+    }
 
-        let anchors = ssd_generate_anchors(&ssd_opts);
-        let model = FlatBufferModel::build_from_file(model_path_buf.clone())?;
+    fn from_model(
+        interpreters: InterpreterPool, ssd_opts: &SSDOptions, model_path: Option<PathBuf>,
+    ) -> Result<FaceDetection, Error> {
+        let anchors = ssd_generate_anchors(ssd_opts);
 
         Ok(FaceDetection {
-            model_path: model_path_buf,
-            model,
+            model_path,
+            interpreters,
             anchors,
+            options: FaceDetectionOptions::default(),
         })
     }
 
+    /// Replace the default post-processing options.
+    pub fn with_options(mut self, options: FaceDetectionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Return the post-processing options in use.
+    pub fn options(&self) -> &FaceDetectionOptions {
+        &self.options
+    }
+
+    /// Set the number of interpreters kept by the model.
+    /// Inference from multiple threads is serialized per interpreter, so use one
+    /// interpreter per thread that calls `infer` concurrently.
+    pub fn with_pool_size(mut self, size: usize) -> Result<Self, Error> {
+        self.interpreters.resize(size)?;
+        Ok(self)
+    }
+
+    /// Number of interpreters in the pool.
+    pub fn pool_size(&self) -> usize {
+        self.interpreters.len()
+    }
+
     /// Run inference and return detections from a given image
     /// * Args:
-    ///     - image (`Mat`): OpenCV matrix.
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
     ///     - roi (`Rect`): Optional region within the image that may
     ///                 contain faces.
     ///
     /// * Returns:
     ///     (`Vec<Detection>`) List of detection results with relative coordinates.
-    pub fn infer(&self, image: &Mat, roi: Option<Rect>) -> Result<Vec<Detection>, Error> {
-        // Init model interpreter
-        let resolver = BuiltinOpResolver::default();
-        let builder = InterpreterBuilder::new(&self.model, &resolver)?;
-        let mut interpreter = builder.build()?;
-        interpreter.allocate_tensors()?;
+    pub fn infer(&self, image: &impl ImageInput, roi: Option<Rect>) -> Result<Vec<Detection>, Error> {
+        // Borrow a model interpreter
+        let mut interpreter = self.interpreters.acquire()?;
+        let mut detections = self.infer_images(&mut interpreter, &[image], roi)?;
+        self.filter_detections(detections.remove(0), image, roi)
+    }
+
+    /// Detect faces over the overlapping tiles of an image pyramid.
+    /// Small faces in large images (group photos, high resolution video) are
+    /// lost when the whole image is downsampled to the model input size;
+    /// tiles keep them at a detectable size. Detections of all tiles are mapped
+    /// back to full-image coordinates and merged with non-maximum suppression.
+    /// * Args:
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
+    ///     - tiling (`&TilingOptions`): Pyramid scales and tile overlap.
+    ///
+    /// * Returns:
+    ///     (`Vec<Detection>`) List of detection results with coordinates relative to the image.
+    pub fn infer_tiled(&self, image: &impl ImageInput, tiling: &TilingOptions) -> Result<Vec<Detection>, Error> {
+        let image_size = image.image_size()?;
+        let tiles = tiling.tiles(image_size)?;
+
+        let mut interpreter = self.interpreters.acquire()?;
+        let mut detections = Vec::new();
+        for tile in tiles {
+            for detection in self.infer_images(&mut interpreter, &[image], Some(tile))?.remove(0) {
+                detections.push(detection_from_roi(&detection, &tile, image_size));
+            }
+        }
+        drop(interpreter);
+
+        let detections = non_maximum_suppression(
+            detections,
+            self.options.min_suppression_threshold,
+            Some(self.options.min_score),
+            self.options.weighted_nms,
+        );
+        self.filter_detections(detections, image, None)
+    }
+
+    /// Detect faces in arbitrarily oriented images, e.g. scans or photos
+    /// without orientation metadata. The model only finds roughly upright
+    /// faces, so it is run on rotated views of the whole image; detections of
+    /// all views are mapped back to image coordinates and merged with
+    /// non-maximum suppression.
+    /// * Args:
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
+    ///     - rotations (`&[f64]`): Face orientations to search for, in degrees clockwise,
+    ///       e.g. `RIGHT_ANGLE_ROTATIONS`.
+    ///
+    /// * Returns:
+    ///     (`Vec<Detection>`) List of detection results with coordinates relative to the image.
+    pub fn infer_rotated(&self, image: &impl ImageInput, rotations: &[f64]) -> Result<Vec<Detection>, Error> {
+        let image_size = image.image_size()?;
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        if width <= 0.0 || height <= 0.0 {
+            return Err(Error::msg("image must not be empty"));
+        }
+
+        let mut interpreter = self.interpreters.acquire()?;
+        let mut detections = Vec::new();
+        for &degrees in rotations {
+            // smallest rotated ROI covering the whole image
+            let rotation = degrees.to_radians();
+            let (sin, cos) = (rotation.sin().abs(), rotation.cos().abs());
+            let roi = Rect::new(
+                0.5,
+                0.5,
+                (width * cos + height * sin) / width,
+                (width * sin + height * cos) / height,
+                rotation,
+                true,
+            );
+            for detection in self.infer_images(&mut interpreter, &[image], Some(roi))?.remove(0) {
+                detections.push(detection_from_roi(&detection, &roi, image_size));
+            }
+        }
+        drop(interpreter);
+
+        let detections = non_maximum_suppression(
+            detections,
+            self.options.min_suppression_threshold,
+            Some(self.options.min_score),
+            self.options.weighted_nms,
+        );
+        self.filter_detections(detections, image, None)
+    }
+
+    /// Run inference on a batch of images, e.g. the photos of an archive.
+    /// The model is invoked once for the whole batch if it supports a dynamic
+    /// batch size; otherwise the images are processed one after another.
+    /// * Args:
+    ///     - images (`&[T]`): Images to process.
+    ///
+    /// * Returns:
+    ///     (`Vec<Vec<Detection>>`) Detection results of every image with relative coordinates.
+    pub fn infer_batch<T: ImageInput>(&self, images: &[T]) -> Result<Vec<Vec<Detection>>, Error> {
+        let images: Vec<&T> = images.iter().collect();
+        if images.len() > 1 {
+            let mut interpreter = self.interpreters.acquire()?;
+            if interpreter.set_batch_size(images.len())? {
+                let detections = self.infer_images(&mut interpreter, &images, None);
+                interpreter.set_batch_size(1)?;
+                return detections?
+                    .into_iter()
+                    .zip(&images)
+                    .map(|(detections, image)| self.filter_detections(detections, *image, None))
+                    .collect();
+            }
+        }
+        images.iter().map(|image| self.infer(*image, None)).collect()
+    }
 
+    /// Run the model on a batch of images; the batch size of the interpreter
+    /// must match the number of images. Detections are not filtered yet.
+    fn infer_images<T: ImageInput>(
+        &self, interpreter: &mut PooledInterpreter, images: &[&T], roi: Option<Rect>,
+    ) -> Result<Vec<Vec<Detection>>, Error> {
         // Get model input image shape
         let input_details = interpreter.get_input_details()?;
         let input_shape = input_details[0].dims.clone();
 
-        // convert image to opencv matrix
         let (height, width) = (input_shape[1], input_shape[2]);
 
-        let image_data = image_to_tensor(&image, roi, Some((width as i32, height as i32)), true, (-1.0, 1.0), false)?;
-
-        // Add additional axis
-        let input_data = image_data
-            .tensor_data
-            .into_dimensionality::<ndarray::IxDyn>()
-            .unwrap()
-            .insert_axis(Axis(0));
-
-        // Infer model with input data
+        // Convert the images straight into the model input tensor and infer
         let inputs = interpreter.inputs().to_vec();
         let input_index = inputs[0];
-        let sub_tensor: Vec<f32> = input_data.into_iter().collect();
-        interpreter
-            .tensor_data_mut(input_index)?
-            .copy_from_slice(&sub_tensor);
+        let paddings = interpreter.write_input(input_index, |input| {
+            batch_items(input, images.len())?
+                .zip(images)
+                .map(|(input, image)| {
+                    image.write_tensor(input, roi, Some((width as i32, height as i32)), true, (-1.0, 1.0), false)
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        })?;
         interpreter.invoke()?;
 
         // retrieve outputs
@@ -248,21 +523,70 @@ impl FaceDetection {
             .ok_or(Error::msg("missing score outputs info"))?;
 
         // Retrieve raw detection boxes and convert to Array3<f32>
-        let raw_boxes_s: &[f32] = interpreter.tensor_data(bbox_index).unwrap();
+        let raw_boxes_s = interpreter.read_output(bbox_index)?;
         let raw_boxes: Array3<f32> =
-            Array3::from_shape_vec((bbox_info.dims[0], bbox_info.dims[1], bbox_info.dims[2]), raw_boxes_s.to_vec())?;
+            Array3::from_shape_vec((bbox_info.dims[0], bbox_info.dims[1], bbox_info.dims[2]), raw_boxes_s)?;
 
-        let raw_scores_s: &[f32] = interpreter.tensor_data(score_index).unwrap();
+        let raw_scores_s = interpreter.read_output(score_index)?;
         let raw_scores =
-            Array::from_shape_vec((score_info.dims[0], score_info.dims[1], score_info.dims[2]), raw_scores_s.to_vec())?;
+            Array::from_shape_vec((score_info.dims[0], score_info.dims[1], score_info.dims[2]), raw_scores_s)?;
 
-        let boxes = self.decode_boxes(raw_boxes, input_shape[1] as f32)?;
-        let scores = self.get_sigmoid_score(raw_scores)?;
+        self.decode_batch(raw_boxes, raw_scores, paddings, input_shape[1] as f32)
+    }
+
+    /// Convert the raw outputs of a batch into the detections of every item and
+    /// remove the letterbox padding of each item. Detections are not filtered yet.
+    fn decode_batch(
+        &self, raw_boxes: Array3<f32>, raw_scores: Array3<f32>, paddings: Vec<(f64, f64, f64, f64)>, scale: f32,
+    ) -> Result<Vec<Vec<Detection>>, Error> {
+        if raw_boxes.len_of(Axis(0)) != paddings.len() || raw_scores.len_of(Axis(0)) != paddings.len() {
+            return Err(Error::msg("batch size of the model outputs does not match the number of images"));
+        }
 
-        let detections = self.convert_to_detections(boxes, scores)?;
-        let pruned_detections = non_maximum_suppression(detections, MIN_SUPPRESSION_THRESHOLD, Some(MIN_SCORE), true);
+        let mut results = Vec::with_capacity(paddings.len());
+        for (i, padding) in paddings.into_iter().enumerate() {
+            let raw_boxes = raw_boxes.index_axis(Axis(0), i).insert_axis(Axis(0)).to_owned();
+            let raw_scores = raw_scores.index_axis(Axis(0), i).insert_axis(Axis(0)).to_owned();
 
-        let detections = detection_letterbox_removal(pruned_detections, image_data.padding);
+            let boxes = self.decode_boxes(raw_boxes, scale)?;
+            let scores = self.get_sigmoid_score(raw_scores)?;
+
+            let detections = self.convert_to_detections(boxes, scores)?;
+            let pruned_detections = non_maximum_suppression(
+                detections,
+                self.options.min_suppression_threshold,
+                Some(self.options.min_score),
+                self.options.weighted_nms,
+            );
+
+            results.push(detection_letterbox_removal(pruned_detections, padding));
+        }
+        Ok(results)
+    }
+
+    /// Drop faces below the minimum face size and keep at most `max_num_faces` detections.
+    fn filter_detections(
+        &self, detections: Vec<Detection>, image: &impl ImageInput, roi: Option<Rect>,
+    ) -> Result<Vec<Detection>, Error> {
+        let mut detections = detections;
+
+        if let Some(min_face_size) = self.options.min_face_size {
+            let (image_width, image_height) = image.image_size()?;
+            let image_size = (image_width as f64, image_height as f64);
+            // detections are relative to the ROI, if any
+            let (width, height) = match roi {
+                Some(roi) => roi.scaled(image_size, false).size(),
+                None => image_size,
+            };
+            detections.retain(|detection| {
+                let bbox = detection.bbox();
+                bbox.width() * width >= min_face_size && bbox.height() * height >= min_face_size
+            });
+        }
+
+        if let Some(max_num_faces) = self.options.max_num_faces {
+            detections.truncate(max_num_faces);
+        }
         Ok(detections)
     }
 
@@ -323,7 +647,8 @@ impl FaceDetection {
         }
 
         let mut detections: Vec<Detection> = Vec::new();
-        let score_above_threshold = scores.mapv(|score| score > MIN_SCORE);
+        let min_score = self.options.min_score;
+        let score_above_threshold = scores.mapv(|score| score > min_score);
         let mut filtered_scores_idx: Vec<usize> = Vec::new();
         for ((_, j, _), &is_true) in score_above_threshold.indexed_iter() {
             if is_true {
@@ -362,6 +687,47 @@ impl FaceDetection {
     }
 }
 
+/// Map a detection relative to a normalized, possibly rotated ROI to image coordinates.
+/// The bounding box of the result encloses the mapped corners of the original box.
+fn detection_from_roi(detection: &Detection, roi: &Rect, image_size: (i32, i32)) -> Detection {
+    let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+    let roi = roi.scaled((width, height), false);
+    let (sin, cos) = roi.rotation.sin_cos();
+    let map = |u: f64, v: f64| -> (f64, f64) {
+        let (dx, dy) = ((u - 0.5) * roi.width, (v - 0.5) * roi.height);
+        ((roi.x_center + dx * cos - dy * sin) / width, (roi.y_center + dx * sin + dy * cos) / height)
+    };
+
+    let mut data = detection.data.clone();
+    let bbox = detection.bbox();
+    let corners = [(bbox.xmin, bbox.ymin), (bbox.xmax, bbox.ymin), (bbox.xmax, bbox.ymax), (bbox.xmin, bbox.ymax)]
+        .map(|(u, v)| map(u, v));
+    data[[0, 0]] = corners.iter().map(|p| p.0).fold(f64::INFINITY, f64::min) as f32;
+    data[[0, 1]] = corners.iter().map(|p| p.1).fold(f64::INFINITY, f64::min) as f32;
+    data[[1, 0]] = corners.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max) as f32;
+    data[[1, 1]] = corners.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max) as f32;
+    for mut point in data.rows_mut().into_iter().skip(2) {
+        let (x, y) = map(point[0] as f64, point[1] as f64);
+        point[0] = x as f32;
+        point[1] = y as f32;
+    }
+    Detection {
+        data,
+        score: detection.score,
+    }
+}
+
+/// Return the model file name and SSD anchor options of a model type.
+fn model_spec(model_type: &FaceDetectionModel) -> (&'static str, SSDOptions) {
+    match model_type {
+        FaceDetectionModel::FrontCamera => (MODEL_NAME_FRONT, SSDOptions::new_front()),
+        FaceDetectionModel::BackCamera => (MODEL_NAME_BACK, SSDOptions::new_back()),
+        FaceDetectionModel::Short => (MODEL_NAME_SHORT, SSDOptions::new_short()),
+        FaceDetectionModel::Full => (MODEL_NAME_FULL, SSDOptions::new_full()),
+        FaceDetectionModel::FullSparse => (MODEL_NAME_FULL_SPARSE, SSDOptions::new_full()),
+    }
+}
+
 /// (reference: mediapipe/calculators/tflite/ssd_anchors_calculator.cc)
 fn ssd_generate_anchors(opts: &SSDOptions) -> Array2<f32> {
     let mut layer_id = 0;
@@ -416,6 +782,9 @@ fn ssd_generate_anchors(opts: &SSDOptions) -> Array2<f32> {
 mod tests {
     use super::*;
     use crate::face_detection_lite::render::render_to_image;
+    #[cfg(feature = "opencv")]
+    use crate::face_detection_lite::utils::convert_image_to_mat;
+    use crate::face_detection_lite::utils::decode_image;
 
     #[test]
     fn test_ndarray() {
@@ -437,4 +806,245 @@ mod tests {
             res.push((x[0], x[1]));
         }
     }
+
+    #[test]
+    #[cfg(feature = "opencv")]
+    fn test_face_detection_options() {
+        let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
+        let image = convert_image_to_mat(im_bytes).unwrap();
+
+        let options = FaceDetectionOptions::default()
+            .with_min_score(0.1)
+            .with_weighted_nms(false)
+            .with_max_num_faces(1);
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None)
+            .unwrap()
+            .with_options(options);
+        let faces = face_detection.infer(&image, None).unwrap();
+        assert_eq!(faces.len(), 1);
+
+        let options = FaceDetectionOptions::default().with_min_face_size(100_000.0);
+        let face_detection = face_detection.with_options(options);
+        let faces = face_detection.infer(&image, None).unwrap();
+        assert!(faces.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "opencv")]
+    fn test_face_detection_from_buffer() {
+        let model_bytes: &[u8] = include_bytes!("../../models/face_detection_back.tflite");
+        let face_detection = FaceDetection::from_buffer(FaceDetectionModel::BackCamera, model_bytes).unwrap();
+
+        let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
+        let image = convert_image_to_mat(im_bytes).unwrap();
+        let faces = face_detection.infer(&image, None).unwrap();
+
+        let expected = FaceDetection::new(FaceDetectionModel::BackCamera, None)
+            .unwrap()
+            .infer(&image, None)
+            .unwrap();
+        assert_eq!(faces.len(), expected.len());
+        assert_eq!(faces[0].data, expected[0].data);
+    }
+
+    #[test]
+    #[cfg(feature = "opencv")]
+    fn test_face_detection_pool() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None)
+            .unwrap()
+            .with_pool_size(2)
+            .unwrap();
+
+        let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
+        let image = convert_image_to_mat(im_bytes).unwrap();
+        let expected = face_detection.infer(&image, None).unwrap();
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let image = convert_image_to_mat(im_bytes).unwrap();
+                        face_detection.infer(&image, None).unwrap()
+                    })
+                })
+                .collect();
+            for handle in handles {
+                let faces = handle.join().unwrap();
+                assert_eq!(faces.len(), expected.len());
+                assert_eq!(faces[0].data, expected[0].data);
+            }
+        });
+    }
+
+    #[test]
+    fn test_face_detection_decode_batch() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let num_anchors = face_detection.anchors.nrows();
+        let scale = 256.0;
+
+        // one confident face per item at a different anchor, with a box of 32 pixels
+        let mut raw_boxes = Array3::<f32>::zeros((2, num_anchors, 16));
+        let mut raw_scores = Array3::<f32>::from_elem((2, num_anchors, 1), -10.0);
+        for (item, anchor) in [(0, 100), (1, 500)] {
+            raw_boxes[[item, anchor, 2]] = 32.0;
+            raw_boxes[[item, anchor, 3]] = 32.0;
+            raw_scores[[item, anchor, 0]] = 10.0;
+        }
+        let paddings = vec![(0.0, 0.0, 0.0, 0.0), (0.0, 0.25, 0.0, 0.25)];
+        let batch = face_detection
+            .decode_batch(raw_boxes.clone(), raw_scores.clone(), paddings.clone(), scale)
+            .unwrap();
+        assert_eq!(batch.len(), 2);
+
+        // every item is decoded as if it was inferred on its own
+        for (item, detections) in batch.iter().enumerate() {
+            let single = face_detection
+                .decode_batch(
+                    raw_boxes.index_axis(Axis(0), item).insert_axis(Axis(0)).to_owned(),
+                    raw_scores.index_axis(Axis(0), item).insert_axis(Axis(0)).to_owned(),
+                    vec![paddings[item]],
+                    scale,
+                )
+                .unwrap();
+            assert_eq!(detections.len(), 1);
+            assert_eq!(detections[0].data, single[0][0].data);
+        }
+        assert_ne!(batch[0][0].data, batch[1][0].data);
+        assert!((batch[1][0].bbox().height() - 2.0 * batch[0][0].bbox().height()).abs() < 1e-4);
+
+        assert!(face_detection.decode_batch(raw_boxes, raw_scores, vec![paddings[0]], scale).is_err());
+    }
+
+    #[test]
+    fn test_face_detection_pool_size() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        assert_eq!(face_detection.pool_size(), 1);
+        let face_detection = face_detection.with_pool_size(3).unwrap();
+        assert_eq!(face_detection.pool_size(), 3);
+        let face_detection = face_detection.with_pool_size(2).unwrap();
+        assert_eq!(face_detection.pool_size(), 2);
+        assert!(face_detection.with_pool_size(0).is_err());
+    }
+
+    #[test]
+    fn test_face_detection_dynamic_image() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
+        let image = decode_image(im_bytes).unwrap();
+        let faces = face_detection.infer(&image, None).unwrap();
+        assert!(!faces.is_empty());
+
+        #[cfg(feature = "opencv")]
+        {
+            let image = convert_image_to_mat(im_bytes).unwrap();
+            let expected = face_detection.infer(&image, None).unwrap();
+            assert_eq!(faces.len(), expected.len());
+            let (bbox, expected_bbox) = (faces[0].bbox(), expected[0].bbox());
+            assert!((bbox.xmin - expected_bbox.xmin).abs() < 0.01);
+            assert!((bbox.ymin - expected_bbox.ymin).abs() < 0.01);
+            assert!((bbox.xmax - expected_bbox.xmax).abs() < 0.01);
+            assert!((bbox.ymax - expected_bbox.ymax).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_face_detection_batch() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let images = vec![
+            decode_image(include_bytes!("../../test_data/man.jpg")).unwrap(),
+            decode_image(include_bytes!("../../test_data/russ_cox_1.jpg")).unwrap(),
+            decode_image(include_bytes!("../../test_data/russ_cox_2.jpg")).unwrap(),
+        ];
+
+        let batch = face_detection.infer_batch(&images).unwrap();
+        assert_eq!(batch.len(), images.len());
+        for (faces, image) in batch.iter().zip(&images) {
+            let expected = face_detection.infer(image, None).unwrap();
+            assert_eq!(faces.len(), expected.len());
+            for (face, expected) in faces.iter().zip(&expected) {
+                assert!((face.score - expected.score).abs() < 1e-4);
+                assert!((face.bbox().xmin - expected.bbox().xmin).abs() < 1e-4);
+                assert!((face.bbox().ymax - expected.bbox().ymax).abs() < 1e-4);
+            }
+        }
+
+        // the single image path still works after a batch
+        assert!(!face_detection.infer(&images[0], None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tiling_options() {
+        assert_eq!(tile_starts(100., 100., 75.), vec![0.]);
+        assert_eq!(tile_starts(250., 100., 75.), vec![0., 75., 150.]);
+
+        // 1 x 3 tiles of 200 px and 3 x 5 tiles of 100 px
+        let tiles = TilingOptions::default().tiles((400, 200)).unwrap();
+        assert_eq!(tiles.len(), 3 + 3 * 5);
+        let first = tiles[0];
+        assert!((first.x_center - 0.25).abs() < 1e-9 && (first.width - 0.5).abs() < 1e-9);
+        assert!((first.height - 1.).abs() < 1e-9);
+        let last = tiles[tiles.len() - 1];
+        assert!((last.x_center + last.width / 2. - 1.).abs() < 1e-9);
+        assert!((last.y_center + last.height / 2. - 1.).abs() < 1e-9);
+
+        assert!(TilingOptions::default().with_scales(vec![]).tiles((400, 200)).is_err());
+        assert!(TilingOptions::default().with_overlap(1.).tiles((400, 200)).is_err());
+
+        let detection = Detection::new(vec![0.5, 0.5, 1., 1.], 0.9);
+        let mapped = detection_from_roi(&detection, &Rect::new(0.75, 0.25, 0.5, 0.5, 0., true), (400, 200));
+        assert_eq!(mapped.bbox().xmin, 0.75);
+        assert_eq!(mapped.bbox().ymax, 0.5);
+    }
+
+    #[test]
+    fn test_detection_from_rotated_roi() {
+        // top left quarter of the view with a keypoint at its top left corner
+        let detection = Detection::new(vec![0., 0., 0.5, 0.5, 0., 0.], 0.9);
+
+        // 90 degree view of a 400x200 image covers 200x400 pixels
+        let roi = Rect::new(0.5, 0.5, 0.5, 2., 90f64.to_radians(), true);
+        let mapped = detection_from_roi(&detection, &roi, (400, 200));
+        let bbox = mapped.bbox();
+        assert!((bbox.xmin - 0.5).abs() < 1e-6 && (bbox.xmax - 1.).abs() < 1e-6);
+        assert!((bbox.ymin - 0.).abs() < 1e-6 && (bbox.ymax - 0.5).abs() < 1e-6);
+        let (x, y) = mapped.keypoint(0);
+        assert!((x - 1.).abs() < 1e-6 && y.abs() < 1e-6);
+
+        let roi = Rect::new(0.5, 0.5, 1., 1., 180f64.to_radians(), true);
+        let mapped = detection_from_roi(&detection, &roi, (400, 200));
+        let bbox = mapped.bbox();
+        assert!((bbox.xmin - 0.5).abs() < 1e-6 && (bbox.ymin - 0.5).abs() < 1e-6);
+        let (x, y) = mapped.keypoint(0);
+        assert!((x - 1.).abs() < 1e-6 && (y - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_face_detection_rotated() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let image = decode_image(include_bytes!("../../test_data/man.jpg")).unwrap();
+        let expected = face_detection.infer(&image, None).unwrap();
+        assert!(!expected.is_empty());
+
+        // face lying on its side after rotating the image clockwise
+        let rotated = image.rotate90();
+        let faces = face_detection.infer_rotated(&rotated, &RIGHT_ANGLE_ROTATIONS).unwrap();
+        assert!(!faces.is_empty());
+        let (bbox, expected_bbox) = (faces[0].bbox(), expected[0].bbox());
+        // (x, y) -> (1 - y, x)
+        assert!((bbox.xmin - (1. - expected_bbox.ymax)).abs() < 0.05);
+        assert!((bbox.ymin - expected_bbox.xmin).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_face_detection_tiled() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let image = decode_image(include_bytes!("../../test_data/man.jpg")).unwrap();
+
+        let expected = face_detection.infer(&image, None).unwrap();
+        let faces = face_detection.infer_tiled(&image, &TilingOptions::default()).unwrap();
+        assert!(!faces.is_empty());
+        let (bbox, expected_bbox) = (faces[0].bbox(), expected[0].bbox());
+        assert!((bbox.xmin - expected_bbox.xmin).abs() < 0.05);
+        assert!((bbox.ymin - expected_bbox.ymin).abs() < 0.05);
+    }
 }
@@ -1,15 +1,10 @@
 use std::path::PathBuf;
 use anyhow::Error;
-use nalgebra::ComplexField;
-use ndarray::{Array2, Array3, Axis};
-use opencv::core::{Mat, MatTraitConst, Vec3b, Vector};
-use opencv::imgcodecs::imwrite;
-use opencv::imgproc::resize;
-use tflite::{FlatBufferModel, InterpreterBuilder};
-use tflite::ops::builtin::BuiltinOpResolver;
-use crate::face_detection_lite::face_landmark::{face_detection_to_roi, FaceLandmark};
-use crate::face_detection_lite::transform::{image_to_tensor};
-use crate::face_detection_lite::types::{BBox, ImageTensor, Rect};
+use ndarray::{Array2, Axis};
+use crate::face_detection_lite::face_alignment::{aligned_face_roi, FaceKeypoints};
+use crate::face_detection_lite::input::ImageInput;
+use crate::face_detection_lite::interpreter::{batch_items, InterpreterPool, PooledInterpreter};
+use crate::face_detection_lite::types::{BBox, Rect};
 use crate::face_detection_lite::utils::l2_norm;
 
 enum FeatureCount {
@@ -20,8 +15,8 @@ enum FeatureCount {
 const IMG_SIZE: i32 = 112;
 
 pub struct FaceEmbeddings {
-    model_path: PathBuf,
-    model: FlatBufferModel,
+    model_path: Option<PathBuf>,
+    interpreters: InterpreterPool,
 }
 
 
@@ -35,39 +30,102 @@ impl FaceEmbeddings {
         } else {
             model_path_buf = PathBuf::from("./models/face_embeddings.tflite");
         }
-        let model = FlatBufferModel::build_from_file(model_path_buf.clone())?;
+        let interpreters = InterpreterPool::from_file(&model_path_buf, 1)?;
 
         Ok(FaceEmbeddings {
-            model_path: model_path_buf,
-            model,
+            model_path: Some(model_path_buf),
+            interpreters,
         })
     }
 
-    pub fn infer(&self, image: &Mat, bbox: BBox) -> Result<Array2<f32>, Error> {
-        // Init model interpreter
-        let resolver = BuiltinOpResolver::default();
-        let builder = InterpreterBuilder::new(&self.model, &resolver)?;
-        let mut interpreter = builder.build()?;
-        interpreter.allocate_tensors()?;
+    /// Create the face embeddings model from the content of a `.tflite` file.
+    pub fn from_buffer(buffer: impl Into<Vec<u8>>) -> Result<FaceEmbeddings, Error> {
+        let interpreters = InterpreterPool::new(buffer.into(), 1)?;
 
+        Ok(FaceEmbeddings {
+            model_path: None,
+            interpreters,
+        })
+    }
+
+    /// Set the number of interpreters used to compute embeddings.
+    pub fn with_pool_size(mut self, size: usize) -> Result<Self, Error> {
+        self.interpreters.resize(size)?;
+        Ok(self)
+    }
 
-        let roi_image = crop_image_to_bbox(&image, bbox)?;
-        let image_data = image_to_tensor(&roi_image, None, Some((IMG_SIZE, IMG_SIZE)), false, (0.0, 1.0), false)?;
+    /// Number of interpreters in the pool.
+    pub fn pool_size(&self) -> usize {
+        self.interpreters.len()
+    }
+
+    /// Compute embeddings of the face within a bounding box.
+    /// * Args:
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
+    ///     - bbox (`BBox`): Bounding box of the face in absolute pixel coordinates.
+    ///
+    /// * Returns:
+    ///    `Array2<f32>` - L2 normalized embeddings.
+    pub fn infer(&self, image: &impl ImageInput, bbox: BBox) -> Result<Array2<f32>, Error> {
+        self.infer_roi(image, bbox_roi(&bbox))
+    }
 
-        // Add additional axis
-        let input_data = image_data
-            .tensor_data
-            .into_dimensionality::<ndarray::IxDyn>()
-            .unwrap()
-            .insert_axis(Axis(0));
+    /// Compute embeddings of a batch of faces, e.g. all faces of a photo archive.
+    /// The model is invoked once for the whole batch if it supports a dynamic
+    /// batch size; otherwise the faces are processed one after another.
+    /// * Args:
+    ///     - faces (`&[(&T, BBox)]`): Images and bounding boxes of the faces in absolute
+    ///       pixel coordinates.
+    ///
+    /// * Returns:
+    ///    `Vec<Array2<f32>>` - L2 normalized embeddings of every face.
+    pub fn infer_batch<T: ImageInput>(&self, faces: &[(&T, BBox)]) -> Result<Vec<Array2<f32>>, Error> {
+        let faces: Vec<(&T, Rect)> = faces.iter().map(|(image, bbox)| (*image, bbox_roi(bbox))).collect();
+        if faces.len() > 1 {
+            let mut interpreter = self.interpreters.acquire()?;
+            if interpreter.set_batch_size(faces.len())? {
+                let embeddings = self.infer_rois(&mut interpreter, &faces);
+                interpreter.set_batch_size(1)?;
+                return embeddings;
+            }
+        }
+        faces.iter().map(|(image, roi)| self.infer_roi(*image, *roi)).collect()
+    }
 
-        // Infer model with input data
+    /// Compute embeddings of a face aligned to the canonical 5-point ArcFace template.
+    /// * Args:
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
+    ///     - face (`impl Into<FaceKeypoints>`): `Detection` or 468 face landmarks of the face.
+    ///
+    /// * Returns:
+    ///    `Array2<f32>` - L2 normalized embeddings.
+    pub fn infer_aligned(&self, image: &impl ImageInput, face: impl Into<FaceKeypoints>) -> Result<Array2<f32>, Error> {
+        let roi = aligned_face_roi(&face.into(), image.image_size()?, IMG_SIZE)?;
+        self.infer_roi(image, roi)
+    }
+
+    /// Run the model on a region of the image, resampled to the model input size.
+    fn infer_roi(&self, image: &impl ImageInput, roi: Rect) -> Result<Array2<f32>, Error> {
+        // Borrow a model interpreter
+        let mut interpreter = self.interpreters.acquire()?;
+        let mut embeddings = self.infer_rois(&mut interpreter, &[(image, roi)])?;
+        Ok(embeddings.remove(0))
+    }
+
+    /// Run the model on a batch of image regions; the batch size of the
+    /// interpreter must match the number of regions.
+    fn infer_rois<T: ImageInput>(
+        &self, interpreter: &mut PooledInterpreter, faces: &[(&T, Rect)],
+    ) -> Result<Vec<Array2<f32>>, Error> {
+        // Convert the images straight into the model input tensor and infer
         let inputs = interpreter.inputs().to_vec();
         let input_index = inputs[0];
-        let sub_tensor: Vec<f32> = input_data.into_iter().collect();
-        interpreter
-            .tensor_data_mut(input_index)?
-            .copy_from_slice(&sub_tensor);
+        interpreter.write_input(input_index, |input| {
+            for (input, (image, roi)) in batch_items(input, faces.len())?.zip(faces) {
+                image.write_tensor(input, Some(*roi), Some((IMG_SIZE, IMG_SIZE)), false, (0.0, 1.0), false)?;
+            }
+            Ok(())
+        })?;
         interpreter.invoke()?;
 
         // retrieve outputs
@@ -79,43 +137,59 @@ impl FaceEmbeddings {
             .tensor_info(embeddings_index)
             .ok_or(Error::msg("missing embeddings outputs info"))?;
 
-        let raw_embeddings: &[f32] = interpreter.tensor_data(embeddings_index).unwrap();
+        let raw_embeddings = interpreter.read_output(embeddings_index)?;
         let embeddings: Array2<f32> =
-            Array2::from_shape_vec((bbox_info.dims[0], bbox_info.dims[1]), raw_embeddings.to_vec())?;
-
-        let norm_embeddings = l2_norm(&embeddings);
+            Array2::from_shape_vec((bbox_info.dims[0], bbox_info.dims[1]), raw_embeddings)?;
+        if embeddings.nrows() != faces.len() {
+            return Err(Error::msg("batch size of the model outputs does not match the number of faces"));
+        }
 
-        Ok(norm_embeddings)
+        Ok(normalize_rows(&embeddings))
     }
 }
 
+/// L2 normalize the embeddings of every face (row) of a batch separately.
+fn normalize_rows(embeddings: &Array2<f32>) -> Vec<Array2<f32>> {
+    embeddings
+        .axis_iter(Axis(0))
+        .map(|row| l2_norm(&row.insert_axis(Axis(0)).to_owned()))
+        .collect()
+}
 
-/// `crop_image_to_bbox` crops image to the bounding box region.
-/// * Args:
-///
-///     - image (`&Mat`): Input OpenCV matrix.
-///     - rect (`BBox`): Bounding box of the ROI.
-///
-/// * Returns:
-///    `Mat` - Cropped OpenCV matrix.
-fn crop_image_to_bbox(image: &Mat, rect: BBox) -> Result<Mat, Error> {
-    let cropped_image = Mat::roi(image, opencv::core::Rect {
-        x: rect.xmin as i32,
-        y: rect.ymin as i32,
-        width: (rect.xmax - rect.xmin) as i32,
-        height: (rect.ymax - rect.ymin) as i32,
-    }).unwrap();
-    Ok(cropped_image.clone_pointee())
+/// ROI of a bounding box in absolute pixel coordinates.
+fn bbox_roi(bbox: &BBox) -> Rect {
+    Rect::new(
+        (bbox.xmin + bbox.xmax) / 2.,
+        (bbox.ymin + bbox.ymax) / 2.,
+        bbox.width(),
+        bbox.height(),
+        0.,
+        false,
+    )
 }
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "opencv")]
     use crate::face_detection_lite::face_detection::{FaceDetection, FaceDetectionModel};
+    #[cfg(feature = "opencv")]
     use crate::face_detection_lite::utils::{convert_image_to_mat, similarity_score};
+    #[cfg(feature = "opencv")]
     use opencv::core::MatTraitConst;
 
     #[test]
+    fn test_normalize_rows() {
+        let embeddings = Array2::from_shape_vec((2, 2), vec![3.0, 4.0, 0.0, 2.0]).unwrap();
+        let normalized = normalize_rows(&embeddings);
+        assert_eq!(normalized.len(), 2);
+        assert_eq!(normalized[0], Array2::from_shape_vec((1, 2), vec![0.6, 0.8]).unwrap());
+        assert_eq!(normalized[1], Array2::from_shape_vec((1, 2), vec![0.0, 1.0]).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "opencv")]
     fn test_face_embeddings() {
         let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
 
@@ -144,4 +218,54 @@ mod tests {
         println!("similarity_score: {:?}", similarity_score);
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg(feature = "opencv")]
+    fn test_face_embeddings_aligned() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let face_embeddings = FaceEmbeddings::new(None).unwrap();
+
+        let mut aligned = Vec::new();
+        for im_bytes in [
+            include_bytes!("../../test_data/russ_cox_1.jpg").as_slice(),
+            include_bytes!("../../test_data/russ_cox_2.jpg").as_slice(),
+        ] {
+            let image = convert_image_to_mat(im_bytes).unwrap();
+            let faces = face_detection.infer(&image, None).unwrap();
+            let embeddings = face_embeddings.infer_aligned(&image, faces[0].clone()).unwrap();
+            assert_eq!(embeddings.nrows(), 1);
+            assert!(matches!(embeddings.ncols(), 128 | 512));
+            aligned.push(embeddings.into_raw_vec_and_offset().0);
+        }
+
+        // both photos show the same person
+        let aligned_score = similarity_score(&aligned[0], &aligned[1]);
+        assert!(aligned_score > 0.5, "aligned similarity {aligned_score}");
+    }
+
+    #[test]
+    #[cfg(feature = "opencv")]
+    fn test_face_embeddings_batch() {
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let face_embeddings = FaceEmbeddings::new(None).unwrap();
+
+        let image1 = convert_image_to_mat(include_bytes!("../../test_data/russ_cox_1.jpg")).unwrap();
+        let image2 = convert_image_to_mat(include_bytes!("../../test_data/russ_cox_2.jpg")).unwrap();
+        let mut faces = Vec::new();
+        for image in [&image1, &image2] {
+            let size = image.size().unwrap();
+            let detections = face_detection.infer(image, None).unwrap();
+            faces.push((image, detections[0].bbox().scale((size.width as f64, size.height as f64))));
+        }
+
+        let batch = face_embeddings.infer_batch(&faces).unwrap();
+        assert_eq!(batch.len(), 2);
+        for (embeddings, (image, bbox)) in batch.iter().zip(&faces) {
+            let expected = face_embeddings.infer(*image, *bbox).unwrap();
+            assert_eq!(embeddings.shape(), expected.shape());
+            let (v1, _) = embeddings.clone().into_raw_vec_and_offset();
+            let (v2, _) = expected.into_raw_vec_and_offset();
+            assert!(similarity_score(&v1, &v2) > 0.999);
+        }
+    }
+}
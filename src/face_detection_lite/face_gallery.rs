@@ -0,0 +1,305 @@
+use crate::face_detection_lite::utils::similarity_score;
+use anyhow::Error;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Magic bytes at the start of a gallery file
+const GALLERY_MAGIC: &[u8; 4] = b"FGAL";
+/// Version of the on-disk gallery format
+const GALLERY_VERSION: u32 = 1;
+/// Largest embedding dimension accepted when reading a gallery
+const MAX_EMBEDDING_DIMENSION: usize = 4096;
+/// Longest identity name in bytes accepted when reading a gallery
+const MAX_IDENTITY_LEN: usize = 4096;
+
+/// Identity returned by `FaceGallery::search`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub identity: String,
+    /// Highest cosine similarity between the query and the identity's embeddings.
+    pub score: f32,
+}
+
+/// Database of enrolled identities and their face embeddings.
+///
+/// Each identity holds one or more embeddings, e.g. from `FaceEmbeddings`.
+/// Searching compares a query embedding against all embeddings using cosine
+/// similarity and scores each identity by its best matching embedding.
+///
+/// The gallery is stored in a little-endian binary format:
+/// magic `FGAL`, format version (`u32`), embedding dimension (`u32`), number of
+/// identities (`u32`), followed by each identity's name length (`u32`), UTF-8
+/// name, number of embeddings (`u32`) and embeddings (`f32` each).
+#[derive(Debug, Clone, Default)]
+pub struct FaceGallery {
+    identities: BTreeMap<String, Vec<Vec<f32>>>,
+    dimension: Option<usize>,
+}
+
+impl FaceGallery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of enrolled identities.
+    pub fn len(&self) -> usize {
+        self.identities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.identities.is_empty()
+    }
+
+    /// Dimension of the embeddings, once the first one has been enrolled.
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    /// Names of the enrolled identities in sorted order.
+    pub fn identities(&self) -> Vec<&str> {
+        self.identities.keys().map(|identity| identity.as_str()).collect()
+    }
+
+    /// Embeddings of an identity.
+    pub fn embeddings(&self, identity: &str) -> Option<&[Vec<f32>]> {
+        self.identities.get(identity).map(|embeddings| embeddings.as_slice())
+    }
+
+    /// Add an embedding to an identity; the identity is created if it does not exist.
+    pub fn enroll(&mut self, identity: &str, embedding: &[f32]) -> Result<(), Error> {
+        self.check_dimension(embedding)?;
+        self.identities
+            .entry(identity.to_string())
+            .or_default()
+            .push(embedding.to_vec());
+        self.dimension = Some(embedding.len());
+        Ok(())
+    }
+
+    /// Replace all embeddings of an identity.
+    pub fn update(&mut self, identity: &str, embeddings: Vec<Vec<f32>>) -> Result<(), Error> {
+        if embeddings.is_empty() {
+            return Err(Error::msg("identity requires at least one embedding"));
+        }
+        for embedding in &embeddings {
+            self.check_dimension(embedding)?;
+        }
+        self.dimension = Some(embeddings[0].len());
+        self.identities.insert(identity.to_string(), embeddings);
+        Ok(())
+    }
+
+    /// Remove an identity; returns `false` if it was not enrolled.
+    pub fn remove(&mut self, identity: &str) -> bool {
+        let removed = self.identities.remove(identity).is_some();
+        if self.identities.is_empty() {
+            self.dimension = None;
+        }
+        removed
+    }
+
+    /// Find the identities most similar to an embedding.
+    /// * Args:
+    ///     - embedding (`&[f32]`): Query embedding.
+    ///     - top_k (`usize`): Maximum number of results.
+    ///     - threshold (`Option<f32>`): Minimum cosine similarity of a result.
+    ///
+    /// * Returns:
+    ///     - (`Vec<SearchResult>`) Matching identities sorted by descending score; identities
+    ///       without a finite similarity, e.g. for a zero query embedding, never match.
+    pub fn search(&self, embedding: &[f32], top_k: usize, threshold: Option<f32>) -> Result<Vec<SearchResult>, Error> {
+        self.check_dimension(embedding)?;
+
+        let mut results: Vec<SearchResult> = self
+            .identities
+            .iter()
+            .map(|(identity, embeddings)| SearchResult {
+                identity: identity.clone(),
+                score: embeddings
+                    .iter()
+                    .map(|enrolled| similarity_score(enrolled, embedding))
+                    .filter(|score| score.is_finite())
+                    .fold(f32::NEG_INFINITY, f32::max),
+            })
+            .filter(|result| result.score.is_finite())
+            .filter(|result| threshold.is_none_or(|threshold| result.score >= threshold))
+            .collect();
+
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results.truncate(top_k);
+        Ok(results)
+    }
+
+    /// Save the gallery to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load a gallery from a file written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FaceGallery, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read_from(&mut reader)
+    }
+
+    /// Serialize the gallery into a writer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_all(GALLERY_MAGIC)?;
+        write_u32(writer, GALLERY_VERSION)?;
+        write_u32(writer, self.dimension.unwrap_or(0) as u32)?;
+        write_u32(writer, self.identities.len() as u32)?;
+
+        for (identity, embeddings) in &self.identities {
+            write_u32(writer, identity.len() as u32)?;
+            writer.write_all(identity.as_bytes())?;
+            write_u32(writer, embeddings.len() as u32)?;
+            for value in embeddings.iter().flatten() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserialize a gallery from a reader. Sizes are validated before anything is
+    /// allocated, so truncated or corrupt input fails instead of exhausting memory.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<FaceGallery, Error> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != GALLERY_MAGIC {
+            return Err(Error::msg("not a face gallery file"));
+        }
+
+        let version = read_u32(reader)?;
+        if version != GALLERY_VERSION {
+            return Err(Error::msg(format!("unsupported face gallery version: {:?}", version)));
+        }
+
+        let dimension = read_u32(reader)? as usize;
+        let num_identities = read_u32(reader)?;
+        if num_identities > 0 && !(1..=MAX_EMBEDDING_DIMENSION).contains(&dimension) {
+            return Err(Error::msg(format!("invalid face gallery embedding dimension: {:?}", dimension)));
+        }
+        let mut gallery = FaceGallery::new();
+
+        for _ in 0..num_identities {
+            let name_len = read_u32(reader)? as usize;
+            if name_len > MAX_IDENTITY_LEN {
+                return Err(Error::msg(format!("face gallery identity name too long: {:?}", name_len)));
+            }
+            let mut name = Vec::with_capacity(name_len);
+            reader.take(name_len as u64).read_to_end(&mut name)?;
+            if name.len() != name_len {
+                return Err(Error::msg("unexpected end of face gallery"));
+            }
+            let identity = String::from_utf8(name)?;
+
+            // embeddings are read one by one, so a bogus count fails at the end of the input
+            let num_embeddings = read_u32(reader)?;
+            let mut embeddings = Vec::new();
+            for _ in 0..num_embeddings {
+                let mut embedding = Vec::with_capacity(dimension);
+                for _ in 0..dimension {
+                    let mut value = [0u8; 4];
+                    reader.read_exact(&mut value)?;
+                    embedding.push(f32::from_le_bytes(value));
+                }
+                embeddings.push(embedding);
+            }
+            gallery.update(&identity, embeddings)?;
+        }
+        Ok(gallery)
+    }
+
+    fn check_dimension(&self, embedding: &[f32]) -> Result<(), Error> {
+        if embedding.is_empty() {
+            return Err(Error::msg("embedding must not be empty"));
+        }
+        match self.dimension {
+            Some(dimension) if dimension != embedding.len() => Err(Error::msg(format!(
+                "embedding dimension mismatch: {:?} != {:?}",
+                embedding.len(),
+                dimension
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<(), Error> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut value = [0u8; 4];
+    reader.read_exact(&mut value)?;
+    Ok(u32::from_le_bytes(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_face_gallery() {
+        let mut gallery = FaceGallery::new();
+        gallery.enroll("alice", &[1.0, 0.0, 0.0]).unwrap();
+        gallery.enroll("alice", &[0.8, 0.6, 0.0]).unwrap();
+        gallery.enroll("bob", &[0.0, 1.0, 0.0]).unwrap();
+        gallery.enroll("carol", &[0.0, 0.0, 1.0]).unwrap();
+        assert!(gallery.enroll("dave", &[1.0, 0.0]).is_err());
+
+        let results = gallery.search(&[0.9, 0.1, 0.0], 2, None).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].identity, "alice");
+        assert_eq!(results[1].identity, "bob");
+
+        let results = gallery.search(&[0.9, 0.1, 0.0], 3, Some(0.5)).unwrap();
+        assert_eq!(results.len(), 1);
+
+        // zero and NaN queries have no similarity to any identity
+        assert!(gallery.search(&[0.0, 0.0, 0.0], 3, None).unwrap().is_empty());
+        assert!(gallery.search(&[f32::NAN, 0.0, 0.0], 3, None).unwrap().is_empty());
+
+        assert!(gallery.remove("alice"));
+        assert!(!gallery.remove("alice"));
+        gallery.update("bob", vec![vec![0.6, 0.8, 0.0]]).unwrap();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        gallery.write_to(&mut buffer).unwrap();
+        let loaded = FaceGallery::read_from(&mut buffer.as_slice()).unwrap();
+        assert_eq!(loaded.identities(), vec!["bob", "carol"]);
+        assert_eq!(loaded.embeddings("bob").unwrap(), gallery.embeddings("bob").unwrap());
+        assert_eq!(loaded.dimension(), Some(3));
+
+        buffer[4] = 2;
+        assert!(FaceGallery::read_from(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_face_gallery_corrupt() {
+        let mut gallery = FaceGallery::new();
+        gallery.enroll("alice", &[1.0, 0.0, 0.0]).unwrap();
+        let mut buffer: Vec<u8> = Vec::new();
+        gallery.write_to(&mut buffer).unwrap();
+
+        // truncated input
+        assert!(FaceGallery::read_from(&mut &buffer[..buffer.len() - 1]).is_err());
+
+        // oversized dimension, name length and embedding count
+        for offset in [8, 16, 16 + 4 + 5] {
+            let mut corrupt = buffer.clone();
+            corrupt[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(FaceGallery::read_from(&mut corrupt.as_slice()).is_err());
+        }
+
+        // a zero dimension with identities
+        let mut corrupt = buffer.clone();
+        corrupt[8..12].copy_from_slice(&0u32.to_le_bytes());
+        assert!(FaceGallery::read_from(&mut corrupt.as_slice()).is_err());
+    }
+}
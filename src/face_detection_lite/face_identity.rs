@@ -0,0 +1,376 @@
+use crate::face_detection_lite::kalman::KalmanBoxFilter;
+use crate::face_detection_lite::nms::overlap_similarity;
+use crate::face_detection_lite::types::{BBox, Detection};
+use crate::face_detection_lite::utils::similarity_score;
+use anyhow::Error;
+
+/// Momentum of the running average of a track's embedding
+const EMBEDDING_MOMENTUM: f32 = 0.9;
+
+/// Options of the `IdentityTracker`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdentityTrackerOptions {
+    /// Number of frames a track is kept alive without a matching detection.
+    pub max_age: u32,
+    /// Number of matched frames before a track is reported.
+    pub min_hits: u32,
+    /// Minimum IoU between a predicted track and a detection to associate them.
+    pub min_iou: f64,
+    /// Weight [0, 1] of the embedding similarity in the association score
+    /// when embeddings are provided; the IoU makes up the remainder.
+    pub embedding_weight: f64,
+    /// Minimum cosine similarity to re-identify a retired track.
+    pub min_reid_similarity: f32,
+    /// Number of frames a retired track can be re-identified by its embedding.
+    pub max_reid_age: u32,
+}
+
+impl Default for IdentityTrackerOptions {
+    fn default() -> Self {
+        Self {
+            max_age: 30,
+            min_hits: 3,
+            min_iou: 0.3,
+            embedding_weight: 0.5,
+            min_reid_similarity: 0.6,
+            max_reid_age: 300,
+        }
+    }
+}
+
+/// A face identity followed over a sequence of frames.
+#[derive(Debug, Clone)]
+pub struct Track {
+    /// Unique identifier of the track.
+    pub id: u64,
+    /// Last detection associated with the track.
+    pub detection: Detection,
+    /// Bounding box estimated by the motion model.
+    pub bbox: BBox,
+    /// Number of frames the track was matched to a detection.
+    pub hits: u32,
+    /// Number of frames since the track was created.
+    pub age: u32,
+    /// Number of frames since the track was last matched.
+    pub time_since_update: u32,
+    /// Running average of the face embeddings of the track, if provided.
+    pub embedding: Option<Vec<f32>>,
+}
+
+struct TrackState {
+    track: Track,
+    filter: KalmanBoxFilter,
+}
+
+impl TrackState {
+    fn new(id: u64, detection: &Detection, embedding: Option<&Vec<f32>>) -> Self {
+        let filter = KalmanBoxFilter::new(&detection.bbox());
+        Self {
+            track: Track {
+                id,
+                detection: detection.clone(),
+                bbox: detection.bbox(),
+                hits: 1,
+                age: 0,
+                time_since_update: 0,
+                embedding: embedding.cloned(),
+            },
+            filter,
+        }
+    }
+
+    fn predict(&mut self) {
+        self.filter.predict();
+        self.track.bbox = self.filter.bbox();
+        self.track.age += 1;
+        self.track.time_since_update += 1;
+    }
+
+    fn update(&mut self, detection: &Detection, embedding: Option<&Vec<f32>>) {
+        self.filter.update(&detection.bbox());
+        self.track.bbox = self.filter.bbox();
+        self.track.detection = detection.clone();
+        self.track.hits += 1;
+        self.track.time_since_update = 0;
+
+        if let Some(embedding) = embedding {
+            self.track.embedding = Some(match &self.track.embedding {
+                Some(current) => blend_embeddings(current, embedding),
+                None => embedding.clone(),
+            });
+        }
+    }
+
+    /// Restart a retired track from a re-identified detection.
+    fn revive(&mut self, detection: &Detection, embedding: Option<&Vec<f32>>) {
+        self.filter = KalmanBoxFilter::new(&detection.bbox());
+        self.update(detection, embedding);
+    }
+}
+
+/// Multi-object tracker assigning persistent IDs to face detections.
+///
+/// Detections are associated with existing tracks by the IoU between each
+/// detection and the box predicted by a constant velocity Kalman filter
+/// (SORT), optionally fused with the cosine similarity of face embeddings.
+/// Tracks without a match for `max_age` frames are retired; when embeddings
+/// are provided, retired tracks can be re-identified after an occlusion.
+pub struct IdentityTracker {
+    options: IdentityTrackerOptions,
+    tracks: Vec<TrackState>,
+    retired: Vec<TrackState>,
+    next_id: u64,
+    frame_count: u64,
+}
+
+impl Default for IdentityTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdentityTracker {
+    pub fn new() -> Self {
+        Self {
+            options: IdentityTrackerOptions::default(),
+            tracks: Vec::new(),
+            retired: Vec::new(),
+            next_id: 1,
+            frame_count: 0,
+        }
+    }
+
+    /// Replace the default tracker options.
+    pub fn with_options(mut self, options: IdentityTrackerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> &IdentityTrackerOptions {
+        &self.options
+    }
+
+    /// Return all active tracks, including tentative and temporarily unmatched ones.
+    pub fn tracks(&self) -> Vec<&Track> {
+        self.tracks.iter().map(|state| &state.track).collect()
+    }
+
+    /// Update the tracker with the detections of the next frame.
+    /// * Args:
+    ///     - detections (`&[Detection]`): Face detections of the frame.
+    ///     - embeddings (`Option<&[Vec<f32>]>`): Optional face embeddings, one per detection,
+    ///       e.g. computed with `FaceEmbeddings`.
+    ///
+    /// * Returns:
+    ///     - (`Vec<Track>`) Confirmed tracks matched in this frame.
+    pub fn update(&mut self, detections: &[Detection], embeddings: Option<&[Vec<f32>]>) -> Result<Vec<Track>, Error> {
+        if let Some(embeddings) = embeddings {
+            if embeddings.len() != detections.len() {
+                return Err(Error::msg("number of embeddings must match the number of detections"));
+            }
+        }
+        let embedding = |index: usize| embeddings.map(|embeddings| &embeddings[index]);
+        self.frame_count += 1;
+
+        for state in self.tracks.iter_mut().chain(self.retired.iter_mut()) {
+            state.predict();
+        }
+
+        // Greedy association by descending similarity
+        let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+        for (t, state) in self.tracks.iter().enumerate() {
+            for (d, detection) in detections.iter().enumerate() {
+                let iou = overlap_similarity(&state.track.bbox, &detection.bbox());
+                if iou < self.options.min_iou {
+                    continue;
+                }
+                // degenerate (e.g. zero) embeddings have no similarity; match them by overlap only
+                let similarity = match (&state.track.embedding, embedding(d)) {
+                    (Some(a), Some(b)) => Some(similarity_score(a, b) as f64).filter(|s| s.is_finite()),
+                    _ => None,
+                };
+                let score = match similarity {
+                    Some(similarity) => {
+                        let weight = self.options.embedding_weight;
+                        (1. - weight) * iou + weight * similarity
+                    }
+                    None => iou,
+                };
+                candidates.push((score, t, d));
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut detection_matched = vec![false; detections.len()];
+        for (_, t, d) in candidates {
+            if track_matched[t] || detection_matched[d] {
+                continue;
+            }
+            track_matched[t] = true;
+            detection_matched[d] = true;
+            self.tracks[t].update(&detections[d], embedding(d));
+        }
+
+        for (d, detection) in detections.iter().enumerate() {
+            if detection_matched[d] {
+                continue;
+            }
+            match self.reidentify(embedding(d)) {
+                Some(mut state) => {
+                    state.revive(detection, embedding(d));
+                    self.tracks.push(state);
+                }
+                None => {
+                    self.tracks.push(TrackState::new(self.next_id, detection, embedding(d)));
+                    self.next_id += 1;
+                }
+            }
+        }
+
+        let max_age = self.options.max_age;
+        let min_hits = self.options.min_hits;
+        let (active, expired): (Vec<_>, Vec<_>) = std::mem::take(&mut self.tracks)
+            .into_iter()
+            .partition(|state| state.track.time_since_update <= max_age);
+        self.tracks = active;
+        self.retired.extend(
+            expired
+                .into_iter()
+                .filter(|state| state.track.embedding.is_some() && state.track.hits >= min_hits),
+        );
+        let max_reid_age = max_age + self.options.max_reid_age;
+        self.retired
+            .retain(|state| state.track.time_since_update <= max_reid_age);
+
+        let confirmed = self
+            .tracks
+            .iter()
+            .filter(|state| state.track.time_since_update == 0)
+            .filter(|state| state.track.hits >= min_hits || self.frame_count <= min_hits as u64)
+            .map(|state| state.track.clone())
+            .collect();
+        Ok(confirmed)
+    }
+
+    /// Take the retired track most similar to the embedding, if any is similar enough.
+    fn reidentify(&mut self, embedding: Option<&Vec<f32>>) -> Option<TrackState> {
+        let embedding = embedding?;
+        let (index, similarity) = self
+            .retired
+            .iter()
+            .enumerate()
+            .filter_map(|(n, state)| {
+                let track_embedding = state.track.embedding.as_ref()?;
+                Some((n, similarity_score(track_embedding, embedding)))
+            })
+            .filter(|(_, similarity)| similarity.is_finite())
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        if similarity < self.options.min_reid_similarity {
+            return None;
+        }
+        Some(self.retired.remove(index))
+    }
+}
+
+/// Blend a new embedding into the running average and re-normalize it.
+fn blend_embeddings(current: &[f32], new: &[f32]) -> Vec<f32> {
+    let blended: Vec<f32> = current
+        .iter()
+        .zip(new.iter())
+        .map(|(c, n)| EMBEDDING_MOMENTUM * c + (1. - EMBEDDING_MOMENTUM) * n)
+        .collect();
+    let norm = blended.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0. {
+        blended.iter().map(|x| x / norm).collect()
+    } else {
+        blended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection_at(x: f32, y: f32) -> Detection {
+        Detection::new(vec![x, y, x + 0.2, y + 0.2], 0.9)
+    }
+
+    #[test]
+    fn test_identity_tracker() {
+        let options = IdentityTrackerOptions {
+            max_age: 2,
+            min_hits: 2,
+            ..IdentityTrackerOptions::default()
+        };
+        let mut tracker = IdentityTracker::new().with_options(options);
+
+        // two faces moving to the right keep their ids
+        let mut ids = Vec::new();
+        for frame in 0..5 {
+            let dx = frame as f32 * 0.01;
+            let detections = vec![detection_at(0.1 + dx, 0.1), detection_at(0.6 + dx, 0.5)];
+            let tracks = tracker.update(&detections, None).unwrap();
+            assert_eq!(tracks.len(), 2);
+            let mut frame_ids: Vec<u64> = tracks.iter().map(|track| track.id).collect();
+            frame_ids.sort();
+            ids.push(frame_ids);
+        }
+        assert!(ids.iter().all(|frame_ids| *frame_ids == ids[0]));
+
+        // the second face disappears and its track is retired after max_age frames
+        for _ in 0..3 {
+            tracker.update(&[detection_at(0.15, 0.1)], None).unwrap();
+        }
+        assert_eq!(tracker.tracks().len(), 1);
+
+        // a face reappearing without an embedding gets a new id
+        let tracks = tracker
+            .update(&[detection_at(0.15, 0.1), detection_at(0.6, 0.5)], None)
+            .unwrap();
+        assert_eq!(tracks.len(), 1);
+        let new_track = tracker.tracks().into_iter().find(|track| track.hits == 1).unwrap();
+        assert!(!ids[0].contains(&new_track.id));
+    }
+
+    #[test]
+    fn test_identity_tracker_reidentification() {
+        let options = IdentityTrackerOptions {
+            max_age: 1,
+            min_hits: 1,
+            ..IdentityTrackerOptions::default()
+        };
+        let mut tracker = IdentityTracker::new().with_options(options);
+        let embedding = vec![0.6, 0.8, 0.0];
+
+        let tracks = tracker
+            .update(&[detection_at(0.1, 0.1)], Some(std::slice::from_ref(&embedding)))
+            .unwrap();
+        let id = tracks[0].id;
+
+        // occluded for a few frames
+        for _ in 0..4 {
+            tracker.update(&[], Some(&[])).unwrap();
+        }
+        assert!(tracker.tracks().is_empty());
+
+        // re-appears elsewhere and is re-identified by its embedding
+        let tracks = tracker
+            .update(&[detection_at(0.6, 0.6)], Some(&[embedding]))
+            .unwrap();
+        assert_eq!(tracks[0].id, id);
+
+        // zero embeddings have NaN similarities; they are tracked by overlap and never re-identified
+        let zero = vec![0.0; 3];
+        let tracks = tracker
+            .update(&[detection_at(0.6, 0.6)], Some(std::slice::from_ref(&zero)))
+            .unwrap();
+        assert_eq!(tracks[0].id, id);
+        for _ in 0..4 {
+            tracker.update(&[], Some(&[])).unwrap();
+        }
+        let tracks = tracker.update(&[detection_at(0.1, 0.1)], Some(&[zero])).unwrap();
+        assert_ne!(tracks[0].id, id);
+    }
+}
@@ -1,13 +1,16 @@
 use crate::face_detection_lite::face_detection::{FaceDetection, FaceDetectionModel, FaceIndex};
+#[cfg(feature = "embedded-models")]
+use crate::face_detection_lite::embedded_models;
+use crate::face_detection_lite::input::ImageInput;
+use crate::face_detection_lite::interpreter::InterpreterPool;
 use crate::face_detection_lite::render::{landmarks_to_render_data, Annotation, Color};
-use crate::face_detection_lite::transform::{bbox_to_roi, image_to_tensor, project_landmarks, sigmoid, SizeMode};
+use crate::face_detection_lite::transform::{
+    bbox_from_landmarks, bbox_to_roi, project_landmarks, sigmoid, SizeMode,
+};
 use crate::face_detection_lite::types::{Detection, Landmark, Rect};
 use anyhow::Error;
-use ndarray::{Array4, Axis};
-use opencv::core::Mat;
+use ndarray::Array4;
 use std::path::PathBuf;
-use tflite::ops::builtin::BuiltinOpResolver;
-use tflite::{FlatBufferModel, InterpreterBuilder};
 
 /// Model for face landmark detection.
 ///
@@ -29,6 +32,9 @@ const NUM_DIMS: i32 = 3;
 const NUM_LANDMARKS: i32 = 468;
 const ROI_SCALE: (f64, f64) = (1.5, 1.5);
 const DETECTION_THRESHOLD: f32 = 0.5;
+/// Landmarks defining the ROI rotation (outer eye corners)
+const ROTATION_START_LANDMARK: usize = 33;
+const ROTATION_END_LANDMARK: usize = 263;
 
 /// face landmark connections
 /// (from face_landmarks_to_render_data_calculator.cc)
@@ -198,8 +204,8 @@ pub fn face_detection_to_roi(face_detection: Detection, image_size: (i32, i32),
 }
 
 pub struct FaceLandmark {
-    model_path: PathBuf,
-    model: FlatBufferModel,
+    model_path: Option<PathBuf>,
+    interpreters: InterpreterPool,
 }
 
 /// `FaceLandmark` detection model as used by Google MediaPipe.
@@ -213,27 +219,69 @@ impl FaceLandmark {
         } else {
             model_path_buf = PathBuf::from("./models/face_landmark.tflite");
         }
-        let model = FlatBufferModel::build_from_file(model_path_buf.clone())?;
+        let interpreters = InterpreterPool::from_file(&model_path_buf, 1)?;
 
         Ok(FaceLandmark {
-            model_path: model_path_buf,
-            model,
+            model_path: Some(model_path_buf),
+            interpreters,
         })
     }
 
+    /// Create the face landmark model from the content of a `.tflite` file.
+    pub fn from_buffer(buffer: impl Into<Vec<u8>>) -> Result<FaceLandmark, Error> {
+        let interpreters = InterpreterPool::new(buffer.into(), 1)?;
+
+        Ok(FaceLandmark {
+            model_path: None,
+            interpreters,
+        })
+    }
+
+    /// Create the face landmark model from the model bundled with the crate.
+    #[cfg(feature = "embedded-models")]
+    pub fn from_embedded() -> Result<FaceLandmark, Error> {
+        Self::from_buffer(embedded_models::FACE_LANDMARK)
+    }
+
+    /// Set the number of landmark interpreters, e.g. to match the number of worker threads.
+    pub fn with_pool_size(mut self, size: usize) -> Result<Self, Error> {
+        self.interpreters.resize(size)?;
+        Ok(self)
+    }
+
+    /// Number of interpreters in the pool.
+    pub fn pool_size(&self) -> usize {
+        self.interpreters.len()
+    }
+
     /// Run inference and return detections from a given image
     /// * Args:
-    ///     - image (`Mat`): opencv mat.
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
     ///     - roi (`Option<Rect>`): Region within the image that contains a face.
     ///
     /// * Returns:
     ///     - (`Vec<Landmark>`) List of face landmarks in normalised coordinates relative to
     ///       the input image, i.e. values ranging from [0, 1].
-    pub fn infer(&self, image: &Mat, roi: Option<Rect>) -> Result<Vec<Landmark>, Error> {
-        let resolver = BuiltinOpResolver::default();
-        let builder = InterpreterBuilder::new(&self.model, &resolver)?;
-        let mut interpreter = builder.build()?;
-        interpreter.allocate_tensors()?;
+    pub fn infer(&self, image: &impl ImageInput, roi: Option<Rect>) -> Result<Vec<Landmark>, Error> {
+        let (landmarks, face_presence) = self.infer_with_presence(image, roi)?;
+        if face_presence <= DETECTION_THRESHOLD {
+            return Ok(Vec::<Landmark>::new());
+        };
+        Ok(landmarks)
+    }
+
+    /// Run inference and return the landmarks together with the face presence score.
+    /// Unlike `infer`, the landmarks are returned regardless of the presence score,
+    /// which allows callers such as trackers to apply their own threshold.
+    /// * Args:
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
+    ///     - roi (`Option<Rect>`): Region within the image that contains a face.
+    ///
+    /// * Returns:
+    ///     - (`(Vec<Landmark>, f32)`) Normalised face landmarks and the probability [0, 1]
+    ///       that the ROI contains a face.
+    pub fn infer_with_presence(&self, image: &impl ImageInput, roi: Option<Rect>) -> Result<(Vec<Landmark>, f32), Error> {
+        let mut interpreter = self.interpreters.acquire()?;
 
         let input_details = interpreter.get_input_details()?;
         let output_details = interpreter.get_output_details()?;
@@ -247,21 +295,13 @@ impl FaceLandmark {
         };
 
         let (height, width) = (input_shape[1], input_shape[2]);
-        let image_data = image_to_tensor(image, roi, Some((width as i32, height as i32)), false, (0., 1.), false)?;
 
-        let input_data = image_data
-            .tensor_data
-            .into_dimensionality::<ndarray::IxDyn>()
-            .unwrap()
-            .insert_axis(Axis(0));
-
-        // Infer model with input data
+        // Convert the image straight into the model input tensor and infer
         let inputs = interpreter.inputs().to_vec();
         let input_index = inputs[0];
-        let sub_tensor: Vec<f32> = input_data.into_iter().collect();
-        interpreter
-            .tensor_data_mut(input_index)?
-            .copy_from_slice(&sub_tensor);
+        let padding = interpreter.write_input(input_index, |input| {
+            image.write_tensor(input, roi, Some((width as i32, height as i32)), false, (0., 1.), false)
+        })?;
         interpreter.invoke()?;
 
         // retrieve outputs
@@ -277,33 +317,56 @@ impl FaceLandmark {
             .tensor_info(raw_face_index)
             .ok_or(Error::msg("missing raw face outputs info"))?;
 
-        let raw_data_s: &[f32] = interpreter.tensor_data(data_index).unwrap();
+        let raw_data_s = interpreter.read_output(data_index)?;
         let raw_data: Array4<f32> = Array4::from_shape_vec(
             (data_info.dims[0], data_info.dims[1], data_info.dims[2], data_info.dims[3]),
-            raw_data_s.to_vec(),
+            raw_data_s,
         )?;
 
-        let raw_face_s: &[f32] = interpreter.tensor_data(raw_face_index).unwrap();
+        let raw_face_s = interpreter.read_output(raw_face_index)?;
         let raw_face: Array4<f32> = Array4::from_shape_vec(
             (raw_face_info.dims[0], raw_face_info.dims[1], raw_face_info.dims[2], raw_face_info.dims[3]),
-            raw_face_s.to_vec(),
+            raw_face_s,
         )?;
 
         let flatten = raw_face.mapv(|x| sigmoid(x)).flatten().to_vec();
         let face_flag = flatten[flatten.len() - 1];
-        if face_flag <= DETECTION_THRESHOLD {
-            return Ok(Vec::<Landmark>::new());
-        };
 
-        project_landmarks(
+        let landmarks = project_landmarks(
             raw_data,
             (width as i32, height as i32),
-            image_data.original_size,
-            image_data.padding,
+            image.image_size()?,
+            padding,
             roi,
             false,
-        )
+        )?;
+        Ok((landmarks, face_flag))
+    }
+}
+
+/// Return a normalized ROI for the next frame from face landmarks.
+///
+/// This is the landmark counterpart of `face_detection_to_roi`, as used by the
+/// MediaPipe face mesh graph to track a face without running face detection:
+/// * Args:
+///     - face_landmarks (`&[Landmark]`): Normalized face landmarks returned by `FaceLandmark`.
+///     - image_size (`(i32, i32)`): A tuple of `(image_width, image_height)` of the input image.
+///
+/// * Returns:
+///     - `Rect`: Normalized ROI for passing to `FaceLandmark`.
+pub fn face_landmarks_to_roi(face_landmarks: &[Landmark], image_size: (i32, i32)) -> Result<Rect, Error> {
+    if face_landmarks.len() < NUM_LANDMARKS as usize {
+        return Err(Error::msg("unexpected number of items in face_landmarks"));
     }
+
+    let bbox = bbox_from_landmarks(face_landmarks)?;
+    let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+    let rotation_keypoints: Vec<(f64, f64)> = [ROTATION_START_LANDMARK, ROTATION_END_LANDMARK]
+        .iter()
+        .map(|&index| (face_landmarks[index].x * width, face_landmarks[index].y * height))
+        .collect();
+
+    bbox_to_roi(bbox, image_size, Some(rotation_keypoints), Some(ROI_SCALE), Some(SizeMode::SquareLong))
 }
 
 /// Convert face landmarks to render data.
@@ -338,7 +401,7 @@ pub fn face_landmarks_to_render_data(
     render_data
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "opencv"))]
 mod tests {
     use super::*;
     use crate::face_detection_lite::face_detection::{FaceDetection, FaceDetectionModel};
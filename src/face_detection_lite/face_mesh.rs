@@ -0,0 +1,172 @@
+use crate::face_detection_lite::face_detection::FaceDetection;
+use crate::face_detection_lite::face_landmark::{face_detection_to_roi, FaceLandmark};
+use crate::face_detection_lite::input::ImageInput;
+use crate::face_detection_lite::iris_landmark::{IrisLandmark, IrisResults};
+use crate::face_detection_lite::transform::SizeMode;
+use crate::face_detection_lite::types::{Detection, Landmark, Rect};
+use anyhow::Error;
+
+/// Stages of the face mesh pipeline to run after face detection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceMeshOptions {
+    /// Run `FaceLandmark` on every detected face.
+    pub landmarks: bool,
+    /// Run `IrisLandmark` on both eyes; requires `landmarks`.
+    pub iris: bool,
+    /// Refine the eye region of the face landmarks with the iris model results.
+    pub refine_landmarks: bool,
+    /// Size mode of the face ROI derived from a detection.
+    pub size_mode: SizeMode,
+}
+
+impl Default for FaceMeshOptions {
+    fn default() -> Self {
+        Self {
+            landmarks: true,
+            iris: true,
+            refine_landmarks: true,
+            size_mode: SizeMode::SquareLong,
+        }
+    }
+}
+
+/// Face mesh results of a single face.
+#[derive(Debug, Clone)]
+pub struct FaceMeshResult {
+    /// Face detection result with normalized coordinates.
+    pub detection: Detection,
+    /// Normalized face ROI passed to `FaceLandmark`.
+    pub roi: Rect,
+    /// 468 normalized face landmarks; empty if the stage was disabled or no
+    /// face was found within the ROI. The eye regions are refined with the
+    /// iris results if `refine_landmarks` is set.
+    pub landmarks: Vec<Landmark>,
+    /// Left eye contour and iris landmarks.
+    pub left_eye: Option<IrisResults>,
+    /// Right eye contour and iris landmarks.
+    pub right_eye: Option<IrisResults>,
+}
+
+/// End-to-end face mesh pipeline.
+/// The pipeline chains `FaceDetection`, `FaceLandmark` and `IrisLandmark`
+/// the same way the MediaPipe face mesh and iris graphs do:
+/// detection → `face_detection_to_roi` → face landmarks →
+/// `iris_roi_from_face_landmarks` → iris landmarks →
+/// `update_face_landmarks_with_iris_results`.
+pub struct FaceMeshPipeline {
+    face_detection: FaceDetection,
+    face_landmark: Option<FaceLandmark>,
+    iris_landmark: Option<IrisLandmark>,
+    options: FaceMeshOptions,
+}
+
+impl FaceMeshPipeline {
+    /// Create a new pipeline from its models.
+    /// Stages without a model are skipped regardless of the options.
+    pub fn new(
+        face_detection: FaceDetection, face_landmark: Option<FaceLandmark>, iris_landmark: Option<IrisLandmark>,
+    ) -> Self {
+        Self {
+            face_detection,
+            face_landmark,
+            iris_landmark,
+            options: FaceMeshOptions::default(),
+        }
+    }
+
+    /// Replace the default pipeline options.
+    pub fn with_options(mut self, options: FaceMeshOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> &FaceMeshOptions {
+        &self.options
+    }
+
+    /// Run the pipeline on every face of an image.
+    /// * Args:
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`; preferably RGB.
+    ///
+    /// * Returns:
+    ///     - (`Vec<FaceMeshResult>`) One result per detected face.
+    pub fn infer(&self, image: &impl ImageInput) -> Result<Vec<FaceMeshResult>, Error> {
+        let image_size = image.image_size()?;
+
+        let faces = self.face_detection.infer(image, None)?;
+
+        faces
+            .into_iter()
+            .map(|face| self.infer_face(image, face, image_size))
+            .collect()
+    }
+
+    /// Run the landmark stages for a single face detection.
+    fn infer_face(&self, image: &impl ImageInput, detection: Detection, image_size: (i32, i32)) -> Result<FaceMeshResult, Error> {
+        let roi = face_detection_to_roi(detection.clone(), image_size, Some(self.options.size_mode))?;
+
+        let mut result = FaceMeshResult {
+            detection,
+            roi,
+            landmarks: Vec::new(),
+            left_eye: None,
+            right_eye: None,
+        };
+
+        let face_landmark = match &self.face_landmark {
+            Some(face_landmark) if self.options.landmarks => face_landmark,
+            _ => return Ok(result),
+        };
+        result.landmarks = face_landmark.infer(image, Some(roi))?;
+        if result.landmarks.is_empty() {
+            return Ok(result);
+        }
+
+        let iris_landmark = match &self.iris_landmark {
+            Some(iris_landmark) if self.options.iris => iris_landmark,
+            _ => return Ok(result),
+        };
+        let eyes = iris_landmark.infer_eyes(image, &result.landmarks)?;
+
+        if self.options.refine_landmarks {
+            result.landmarks = eyes.update_face_landmarks(result.landmarks)?;
+        }
+        result.left_eye = Some(eyes.left);
+        result.right_eye = Some(eyes.right);
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::face_detection_lite::face_detection::FaceDetectionModel;
+    use crate::face_detection_lite::utils::decode_image;
+
+    #[test]
+    fn test_face_mesh_pipeline() {
+        let pipeline = FaceMeshPipeline::new(
+            FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap(),
+            Some(FaceLandmark::new(None).unwrap()),
+            Some(IrisLandmark::new(None).unwrap()),
+        );
+
+        let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
+        let image = decode_image(im_bytes).unwrap();
+
+        let faces = pipeline.infer(&image).unwrap();
+        assert!(!faces.is_empty());
+        assert_eq!(faces[0].landmarks.len(), 468);
+        assert!(faces[0].left_eye.is_some());
+        assert!(faces[0].right_eye.is_some());
+
+        let options = FaceMeshOptions {
+            iris: false,
+            ..FaceMeshOptions::default()
+        };
+        let faces = pipeline.with_options(options).infer(&image).unwrap();
+        assert_eq!(faces[0].landmarks.len(), 468);
+        assert!(faces[0].left_eye.is_none());
+    }
+}
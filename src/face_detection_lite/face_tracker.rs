@@ -0,0 +1,180 @@
+use crate::face_detection_lite::face_detection::FaceDetection;
+use crate::face_detection_lite::face_landmark::{face_detection_to_roi, face_landmarks_to_roi, FaceLandmark};
+use crate::face_detection_lite::input::ImageInput;
+use crate::face_detection_lite::nms::overlap_similarity;
+use crate::face_detection_lite::transform::bbox_from_landmarks;
+use crate::face_detection_lite::types::{Landmark, Rect};
+use anyhow::Error;
+
+/// Options of the `FaceTracker`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceTrackerOptions {
+    /// Maximum number of faces to track. Face detection only runs while fewer
+    /// faces are tracked.
+    pub max_num_faces: usize,
+    /// Minimum face presence score [0, 1] for a face to remain tracked.
+    pub min_presence_score: f32,
+    /// IoU above which a new detection is considered an already tracked face.
+    pub min_similarity_threshold: f64,
+}
+
+impl Default for FaceTrackerOptions {
+    fn default() -> Self {
+        Self {
+            max_num_faces: 1,
+            min_presence_score: 0.5,
+            min_similarity_threshold: 0.5,
+        }
+    }
+}
+
+/// A face tracked by `FaceTracker`.
+#[derive(Debug, Clone)]
+pub struct TrackedFace {
+    /// Normalized ROI the landmarks were computed from.
+    pub roi: Rect,
+    /// 468 normalized face landmarks.
+    pub landmarks: Vec<Landmark>,
+    /// Face presence score [0, 1] reported by the landmark model.
+    pub presence: f32,
+    /// `true` if the face was (re-)detected in this frame rather than tracked.
+    pub detected: bool,
+}
+
+/// Frame-to-frame face tracking as done by the MediaPipe face mesh graph.
+///
+/// The ROI for the next frame is derived from the face landmarks of the
+/// current frame, so `FaceDetection` only runs when fewer than
+/// `max_num_faces` faces are tracked, i.e. on the first frame and whenever
+/// the face presence score of a tracked face drops below the threshold.
+pub struct FaceTracker {
+    face_detection: FaceDetection,
+    face_landmark: FaceLandmark,
+    options: FaceTrackerOptions,
+    rois: Vec<Rect>,
+}
+
+impl FaceTracker {
+    pub fn new(face_detection: FaceDetection, face_landmark: FaceLandmark) -> Self {
+        Self {
+            face_detection,
+            face_landmark,
+            options: FaceTrackerOptions::default(),
+            rois: Vec::new(),
+        }
+    }
+
+    /// Replace the default tracker options.
+    pub fn with_options(mut self, options: FaceTrackerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> &FaceTrackerOptions {
+        &self.options
+    }
+
+    /// Number of faces that will be tracked into the next frame.
+    pub fn num_tracked(&self) -> usize {
+        self.rois.len()
+    }
+
+    /// Drop all tracked faces; the next frame runs face detection.
+    pub fn reset(&mut self) {
+        self.rois.clear();
+    }
+
+    /// Track faces in the next frame of a sequence.
+    /// * Args:
+    ///     - image (`impl ImageInput`): Current frame; preferably RGB.
+    ///
+    /// * Returns:
+    ///     - (`Vec<TrackedFace>`) Faces found in the frame.
+    pub fn track(&mut self, image: &impl ImageInput) -> Result<Vec<TrackedFace>, Error> {
+        let image_size = image.image_size()?;
+
+        let mut faces: Vec<TrackedFace> = Vec::new();
+        for roi in std::mem::take(&mut self.rois) {
+            if let Some(face) = self.infer_roi(image, roi, false)? {
+                faces.push(face);
+            }
+        }
+
+        if faces.len() < self.options.max_num_faces {
+            let detections = self.face_detection.infer(image, None)?;
+            for detection in detections {
+                if faces.len() >= self.options.max_num_faces {
+                    break;
+                }
+
+                let bbox = detection.bbox();
+                let mut is_tracked = false;
+                for face in &faces {
+                    let face_bbox = bbox_from_landmarks(&face.landmarks)?;
+                    if overlap_similarity(&face_bbox, &bbox) > self.options.min_similarity_threshold {
+                        is_tracked = true;
+                        break;
+                    }
+                }
+                if is_tracked {
+                    continue;
+                }
+
+                let roi = face_detection_to_roi(detection, image_size, None)?;
+                if let Some(face) = self.infer_roi(image, roi, true)? {
+                    faces.push(face);
+                }
+            }
+        }
+
+        for face in &faces {
+            self.rois.push(face_landmarks_to_roi(&face.landmarks, image_size)?);
+        }
+
+        Ok(faces)
+    }
+
+    /// Run the landmark model on a ROI and keep the face if it is present.
+    fn infer_roi(&self, image: &impl ImageInput, roi: Rect, detected: bool) -> Result<Option<TrackedFace>, Error> {
+        let (landmarks, presence) = self.face_landmark.infer_with_presence(image, Some(roi))?;
+        if presence < self.options.min_presence_score {
+            return Ok(None);
+        }
+
+        Ok(Some(TrackedFace {
+            roi,
+            landmarks,
+            presence,
+            detected,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::face_detection_lite::face_detection::FaceDetectionModel;
+    use crate::face_detection_lite::utils::decode_image;
+
+    #[test]
+    fn test_face_tracker() {
+        let mut tracker = FaceTracker::new(
+            FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap(),
+            FaceLandmark::new(None).unwrap(),
+        );
+
+        let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
+        let image = decode_image(im_bytes).unwrap();
+
+        let faces = tracker.track(&image).unwrap();
+        assert_eq!(faces.len(), 1);
+        assert!(faces[0].detected);
+        assert_eq!(tracker.num_tracked(), 1);
+
+        // the second frame is tracked from the landmarks of the first one
+        let faces = tracker.track(&image).unwrap();
+        assert_eq!(faces.len(), 1);
+        assert!(!faces[0].detected);
+        assert_eq!(faces[0].landmarks.len(), 468);
+    }
+}
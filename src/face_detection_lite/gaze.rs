@@ -0,0 +1,275 @@
+use crate::face_detection_lite::head_pose::HeadPose;
+use crate::face_detection_lite::iris_landmark::{FocalLength, IrisIndex, IrisResults};
+use anyhow::Error;
+
+/// Eye contour indices (from iris landmark results) of the eye corners
+const EYE_CORNERS: (usize, usize) = (0, 8);
+
+/// Rotation from head model to camera coordinates of a face looking into the camera
+const FRONTAL_ROTATION: [[f64; 3]; 3] = [[1., 0., 0.], [0., -1., 0.], [0., 0., -1.]];
+
+/// Physical screen geometry relative to the camera.
+///
+/// The screen is assumed to lie in the camera's image plane (`z = 0`), as for
+/// a laptop or monitor webcam. Positions are in millimetres as seen by the
+/// user facing the screen, with the origin at the top left screen corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenGeometry {
+    pub width_mm: f64,
+    pub height_mm: f64,
+    /// Horizontal position of the camera from the left screen edge.
+    pub camera_x_mm: f64,
+    /// Vertical position of the camera from the top screen edge; negative above the screen.
+    pub camera_y_mm: f64,
+}
+
+impl Default for ScreenGeometry {
+    /// 15.6" 16:9 laptop screen with the camera centered above the display.
+    fn default() -> Self {
+        Self {
+            width_mm: 345.,
+            height_mm: 194.,
+            camera_x_mm: 172.5,
+            camera_y_mm: -10.,
+        }
+    }
+}
+
+/// Options of the `GazeEstimator`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GazeEstimatorOptions {
+    /// Eyeball radius relative to the distance between the eye corners.
+    pub eyeball_radius_ratio: f64,
+    /// Focal length of the camera; approximated by the image width if `None`.
+    pub focal_length: Option<FocalLength>,
+    pub screen: ScreenGeometry,
+}
+
+impl Default for GazeEstimatorOptions {
+    fn default() -> Self {
+        Self {
+            eyeball_radius_ratio: 0.42,
+            focal_length: None,
+            screen: ScreenGeometry::default(),
+        }
+    }
+}
+
+/// Gaze of a single eye.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EyeGaze {
+    /// Horizontal eye-in-head rotation in degrees; positive looks towards the image left.
+    pub yaw: f64,
+    /// Vertical eye-in-head rotation in degrees; positive looks down.
+    pub pitch: f64,
+    /// Unit gaze vector in camera coordinates (`x` right, `y` down, `z` away from the camera).
+    pub direction: [f64; 3],
+    /// Estimated iris position in camera coordinates in mm.
+    pub origin: [f64; 3],
+}
+
+/// Gaze of both eyes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GazeResult {
+    pub left_eye: EyeGaze,
+    pub right_eye: EyeGaze,
+    /// Mean unit gaze vector of both eyes in camera coordinates.
+    pub direction: [f64; 3],
+    /// Midpoint between both irises in camera coordinates in mm.
+    pub origin: [f64; 3],
+    /// Normalized gaze point on the screen, e.g. `(0.5, 0.5)` for the screen
+    /// center; values outside [0, 1] are off-screen. `None` if the gaze does
+    /// not point towards the screen plane.
+    pub screen_point: Option<(f64, f64)>,
+}
+
+/// Estimate the gaze direction from iris landmarks and head pose.
+///
+/// The iris displacement from the center between the eye corners gives the
+/// eye rotation relative to the head (spherical eyeball model), which is
+/// combined with the head rotation to obtain the gaze in camera coordinates.
+/// The estimate is uncalibrated; expect errors of several degrees.
+#[derive(Debug, Clone, Default)]
+pub struct GazeEstimator {
+    options: GazeEstimatorOptions,
+}
+
+impl GazeEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the default estimator options.
+    pub fn with_options(mut self, options: GazeEstimatorOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn options(&self) -> &GazeEstimatorOptions {
+        &self.options
+    }
+
+    /// Estimate the gaze of both eyes.
+    /// * Args:
+    ///     - left_eye (`&IrisResults`): Iris results of the left eye (`is_right_eye = false`).
+    ///     - right_eye (`&IrisResults`): Iris results of the right eye (`is_right_eye = true`).
+    ///     - head_pose (`Option<&HeadPose>`): Head pose from `estimate_head_pose`; a frontal
+    ///       face is assumed if `None`.
+    ///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+    ///
+    /// * Returns:
+    ///     - (`GazeResult`) Per-eye and combined gaze and the on-screen gaze point.
+    pub fn estimate(
+        &self, left_eye: &IrisResults, right_eye: &IrisResults, head_pose: Option<&HeadPose>, image_size: (i32, i32),
+    ) -> Result<GazeResult, Error> {
+        let left_eye = self.estimate_eye(left_eye, head_pose, image_size)?;
+        let right_eye = self.estimate_eye(right_eye, head_pose, image_size)?;
+
+        let direction = normalize([
+            left_eye.direction[0] + right_eye.direction[0],
+            left_eye.direction[1] + right_eye.direction[1],
+            left_eye.direction[2] + right_eye.direction[2],
+        ]);
+        let origin = [
+            (left_eye.origin[0] + right_eye.origin[0]) / 2.,
+            (left_eye.origin[1] + right_eye.origin[1]) / 2.,
+            (left_eye.origin[2] + right_eye.origin[2]) / 2.,
+        ];
+
+        Ok(GazeResult {
+            left_eye,
+            right_eye,
+            direction,
+            origin,
+            screen_point: self.screen_point(origin, direction),
+        })
+    }
+
+    /// Estimate the gaze of a single eye.
+    /// * Args:
+    ///     - eye (`&IrisResults`): Iris results of the eye.
+    ///     - head_pose (`Option<&HeadPose>`): Head pose; a frontal face is assumed if `None`.
+    ///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+    pub fn estimate_eye(
+        &self, eye: &IrisResults, head_pose: Option<&HeadPose>, image_size: (i32, i32),
+    ) -> Result<EyeGaze, Error> {
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        let contour = eye.eyeball_contour();
+        let (a, b) = (contour[EYE_CORNERS.0], contour[EYE_CORNERS.1]);
+        let center = eye.iris(IrisIndex::Center);
+
+        // eye axis pointing towards the image right
+        let mut axis = ((b.x - a.x) * width, (b.y - a.y) * height);
+        if axis.0 < 0. {
+            axis = (-axis.0, -axis.1);
+        }
+        let eye_width = axis.0.hypot(axis.1);
+        if eye_width <= f64::EPSILON {
+            return Err(Error::msg("eye corners must not coincide"));
+        }
+        let axis = (axis.0 / eye_width, axis.1 / eye_width);
+
+        // iris offset along the eye axis and its normal (pointing down)
+        let offset = ((center.x - (a.x + b.x) / 2.) * width, (center.y - (a.y + b.y) / 2.) * height);
+        let dx = offset.0 * axis.0 + offset.1 * axis.1;
+        let dy = -offset.0 * axis.1 + offset.1 * axis.0;
+
+        let radius = self.options.eyeball_radius_ratio * eye_width;
+        let horizontal = (dx / radius).clamp(-1., 1.).asin();
+        let vertical = (dy / radius).clamp(-1., 1.).asin();
+
+        // eye direction in head model coordinates (x right, y up, z out of the face)
+        let model_direction = [
+            horizontal.sin() * vertical.cos(),
+            -vertical.sin(),
+            horizontal.cos() * vertical.cos(),
+        ];
+        let rotation = head_pose.map_or(FRONTAL_ROTATION, |pose| pose.rotation);
+        let direction = normalize(rotate(&rotation, model_direction));
+
+        let focal_length = self.options.focal_length.unwrap_or(FocalLength::Pixels(width));
+        Ok(EyeGaze {
+            yaw: -horizontal.to_degrees(),
+            pitch: vertical.to_degrees(),
+            direction,
+            origin: eye.iris_position(image_size, focal_length),
+        })
+    }
+
+    /// Intersect a gaze ray with the screen plane and return the normalized screen position.
+    fn screen_point(&self, origin: [f64; 3], direction: [f64; 3]) -> Option<(f64, f64)> {
+        if direction[2] >= -f64::EPSILON {
+            return None;
+        }
+        let t = -origin[2] / direction[2];
+        let (x, y) = (origin[0] + t * direction[0], origin[1] + t * direction[1]);
+
+        // camera x points to the user's left when facing the screen
+        let screen = &self.options.screen;
+        Some((
+            (screen.camera_x_mm - x) / screen.width_mm,
+            (screen.camera_y_mm + y) / screen.height_mm,
+        ))
+    }
+}
+
+fn rotate(rotation: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    let mut result = [0.; 3];
+    for (i, value) in result.iter_mut().enumerate() {
+        *value = rotation[i][0] * v[0] + rotation[i][1] * v[1] + rotation[i][2] * v[2];
+    }
+    result
+}
+
+fn normalize(v: [f64; 3]) -> [f64; 3] {
+    let norm = (v[0].powi(2) + v[1].powi(2) + v[2].powi(2)).sqrt();
+    if norm <= f64::EPSILON {
+        return v;
+    }
+    [v[0] / norm, v[1] / norm, v[2] / norm]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::face_detection_lite::types::Landmark;
+
+    fn eye_at(x: f64, iris_dx: f64) -> IrisResults {
+        let mut contour = vec![Landmark::new(x, 0.5, 0.); 71];
+        contour[EYE_CORNERS.0] = Landmark::new(x - 0.015, 0.5, 0.);
+        contour[EYE_CORNERS.1] = Landmark::new(x + 0.015, 0.5, 0.);
+        let cx = x + iris_dx;
+        let iris = vec![
+            Landmark::new(cx, 0.5, 0.),
+            Landmark::new(cx - 0.006, 0.5, 0.),
+            Landmark::new(cx, 0.494, 0.),
+            Landmark::new(cx + 0.006, 0.5, 0.),
+            Landmark::new(cx, 0.506, 0.),
+        ];
+        IrisResults::new(contour, iris).unwrap()
+    }
+
+    #[test]
+    fn test_gaze_estimator() {
+        let estimator = GazeEstimator::new();
+        let image_size = (1000, 1000);
+
+        // looking straight into the camera
+        let gaze = estimator
+            .estimate(&eye_at(0.47, 0.), &eye_at(0.53, 0.), None, image_size)
+            .unwrap();
+        assert!(gaze.left_eye.yaw.abs() < 1e-9 && gaze.left_eye.pitch.abs() < 1e-9);
+        assert!((gaze.direction[2] + 1.).abs() < 1e-9);
+        let (u, v) = gaze.screen_point.unwrap();
+        assert!((u - 0.5).abs() < 1e-6);
+        assert!(v < 0.);
+
+        // irises moved towards the image right
+        let gaze = estimator
+            .estimate(&eye_at(0.47, 0.005), &eye_at(0.53, 0.005), None, image_size)
+            .unwrap();
+        assert!(gaze.left_eye.yaw < 0. && gaze.right_eye.yaw < 0.);
+        assert!(gaze.direction[0] > 0.);
+        assert!(gaze.screen_point.unwrap().0 < 0.5);
+    }
+}
@@ -0,0 +1,419 @@
+use crate::face_detection_lite::render::{Annotation, AnnotationData, Color, Colors, Line};
+use crate::face_detection_lite::types::Landmark;
+use anyhow::Error;
+use nalgebra::{Matrix3, Vector3};
+#[cfg(feature = "opencv")]
+use opencv::calib3d::{rodrigues, solve_pnp, SOLVEPNP_ITERATIVE};
+#[cfg(feature = "opencv")]
+use opencv::core::{Mat, MatTraitConst, Point2f, Point3f, Vector};
+
+/// Number of face landmarks (from face landmark results)
+const NUM_FACE_LANDMARKS: usize = 468;
+
+/// Sparse 3D face model mapped to face mesh landmark indices.
+///
+/// The points are vertices of the MediaPipe canonical face model
+/// (`canonical_face_model.obj`), converted to millimetres and shifted so the
+/// nose tip is the origin. `x` points to the right of the image, `y` up and
+/// `z` towards the camera. Only rigid parts of the face are used, so that
+/// expressions like an open mouth do not affect the pose.
+pub const HEAD_MODEL_POINTS: [(usize, [f64; 3]); 7] = [
+    // nose tip
+    (1, [0.0, 0.0, 0.0]),
+    // nose bridge
+    (6, [0.0, 36.0012, -11.93714]),
+    // forehead
+    (10, [0.0, 93.88643, -29.94069]),
+    // outer eye corners
+    (33, [-44.45859, 37.90856, -43.02182]),
+    (263, [44.45859, 37.90856, -43.02182]),
+    // mouth corners
+    (61, [-24.56206, -32.15756, -31.9172]),
+    (291, [24.56206, -32.15756, -31.9172]),
+];
+
+/// Pinhole camera intrinsics in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraIntrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+}
+
+impl CameraIntrinsics {
+    pub fn new(fx: f64, fy: f64, cx: f64, cy: f64) -> Self {
+        Self { fx, fy, cx, cy }
+    }
+
+    /// Approximate intrinsics of an uncalibrated camera: the focal length
+    /// equals the image width and the principal point is the image center.
+    pub fn from_image_size(image_size: (i32, i32)) -> Self {
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        Self::new(width, width, width / 2.0, height / 2.0)
+    }
+
+    /// Project a point in camera coordinates onto the image (in pixels).
+    pub fn project(&self, point: [f64; 3]) -> (f64, f64) {
+        let z = if point[2].abs() > f64::EPSILON { point[2] } else { f64::EPSILON };
+        (self.fx * point[0] / z + self.cx, self.fy * point[1] / z + self.cy)
+    }
+}
+
+/// Head pose relative to the camera.
+///
+/// The camera looks along `+z` with `x` to the right and `y` down (OpenCV
+/// convention). A face looking straight into the camera has zero angles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadPose {
+    /// Rotation around the vertical axis in degrees; positive turns towards the image left.
+    pub yaw: f64,
+    /// Rotation around the horizontal axis in degrees; positive looks down.
+    pub pitch: f64,
+    /// In-plane rotation in degrees; positive rotates clockwise in the image.
+    pub roll: f64,
+    /// Rotation matrix from model to camera coordinates.
+    pub rotation: [[f64; 3]; 3],
+    /// Translation from the camera to the model origin (nose tip) in model units.
+    pub translation: [f64; 3],
+}
+
+impl HeadPose {
+    /// Create a head pose from a rotation matrix and translation.
+    pub fn new(rotation: [[f64; 3]; 3], translation: [f64; 3]) -> Self {
+        // angles are relative to a face looking into the camera, i.e. the
+        // model rotated by 180° around the x axis
+        let mut r = rotation;
+        for row in r.iter_mut() {
+            row[1] = -row[1];
+            row[2] = -row[2];
+        }
+        let pitch = r[2][1].atan2(r[2][2]);
+        let yaw = (-r[2][0]).atan2((r[2][1].powi(2) + r[2][2].powi(2)).sqrt());
+        let roll = r[1][0].atan2(r[0][0]);
+
+        Self {
+            yaw: yaw.to_degrees(),
+            pitch: pitch.to_degrees(),
+            roll: roll.to_degrees(),
+            rotation,
+            translation,
+        }
+    }
+
+    /// Transform a point from model to camera coordinates.
+    pub fn transform(&self, point: [f64; 3]) -> [f64; 3] {
+        let r = self.rotation;
+        let t = self.translation;
+        let mut result = [0.0; 3];
+        for (i, value) in result.iter_mut().enumerate() {
+            *value = r[i][0] * point[0] + r[i][1] * point[1] + r[i][2] * point[2] + t[i];
+        }
+        result
+    }
+}
+
+/// Estimate the head pose from face landmarks using the default `HEAD_MODEL_POINTS`.
+/// * Args:
+///     - face_landmarks (`&[Landmark]`): Normalized face landmarks returned by `FaceLandmark`.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///     - camera (`Option<CameraIntrinsics>`): Camera intrinsics; approximated from the
+///       image size if `None`.
+///
+/// * Returns:
+///     - (`HeadPose`) Euler angles, rotation matrix and translation of the head.
+pub fn estimate_head_pose(
+    face_landmarks: &[Landmark], image_size: (i32, i32), camera: Option<CameraIntrinsics>,
+) -> Result<HeadPose, Error> {
+    estimate_head_pose_with_model(face_landmarks, image_size, camera, &HEAD_MODEL_POINTS)
+}
+
+/// Estimate the head pose by a rigid fit (Procrustes) of a 3D face model to the
+/// 3D face landmarks. The distance to the camera follows from the fitted scale
+/// (weak perspective), which is accurate as long as the face is small compared
+/// to its distance.
+/// * Args:
+///     - face_landmarks (`&[Landmark]`): Normalized face landmarks returned by `FaceLandmark`.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///     - camera (`Option<CameraIntrinsics>`): Camera intrinsics; approximated from the
+///       image size if `None`.
+///     - model_points (`&[(usize, [f64; 3])]`): Pairs of landmark index and 3D model
+///       point (`x` right, `y` up, `z` towards the camera); at least 3 points.
+///
+/// * Returns:
+///     - (`HeadPose`) Euler angles, rotation matrix and translation of the head.
+pub fn estimate_head_pose_with_model(
+    face_landmarks: &[Landmark], image_size: (i32, i32), camera: Option<CameraIntrinsics>,
+    model_points: &[(usize, [f64; 3])],
+) -> Result<HeadPose, Error> {
+    if face_landmarks.len() < NUM_FACE_LANDMARKS {
+        return Err(Error::msg("unexpected number of items in face_landmarks"));
+    }
+    if model_points.len() < 3 {
+        return Err(Error::msg("model_points must contain at least 3 items"));
+    }
+
+    let camera = camera.unwrap_or_else(|| CameraIntrinsics::from_image_size(image_size));
+    let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+
+    // landmarks in pixels in the model convention (y up, z towards the camera);
+    // the landmark depth has the same scale as `x`
+    let mut model = Vec::with_capacity(model_points.len());
+    let mut image = Vec::with_capacity(model_points.len());
+    for &(index, point) in model_points {
+        let landmark = face_landmarks
+            .get(index)
+            .ok_or(Error::msg(format!("invalid landmark index: {:?}", index)))?;
+        model.push(Vector3::from(point));
+        image.push(Vector3::new(landmark.x * width, -landmark.y * height, -landmark.z * width));
+    }
+
+    let (scale, rotation, translation) = fit_similarity(&model, &image)
+        .ok_or(Error::msg("failed to solve the head pose"))?;
+
+    // the scale holds at the centroid of the model points, which is placed at the
+    // depth given by the scale; the model origin follows from the rotation
+    let centroid = model.iter().sum::<Vector3<f64>>() / model.len() as f64;
+    let projected = scale * rotation * centroid + translation;
+    let depth = camera.fx / scale;
+    let (u, v) = (projected.x, -projected.y);
+    let centroid_camera = Vector3::new((u - camera.cx) * depth / camera.fx, (v - camera.cy) * depth / camera.fy, depth);
+
+    // model to camera convention (y down, z away from the camera)
+    let flip = Matrix3::from_diagonal(&Vector3::new(1.0, -1.0, -1.0));
+    let rotation = flip * rotation;
+    let origin = centroid_camera - rotation * centroid;
+    let translation = [origin.x, origin.y, origin.z];
+
+    let mut rows = [[0.0; 3]; 3];
+    for (i, row) in rows.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = rotation[(i, j)];
+        }
+    }
+    Ok(HeadPose::new(rows, translation))
+}
+
+/// Least squares similarity transform `target ≈ scale * rotation * source + translation`
+/// between corresponding points (Umeyama).
+fn fit_similarity(source: &[Vector3<f64>], target: &[Vector3<f64>]) -> Option<(f64, Matrix3<f64>, Vector3<f64>)> {
+    let count = source.len() as f64;
+    let source_mean = source.iter().sum::<Vector3<f64>>() / count;
+    let target_mean = target.iter().sum::<Vector3<f64>>() / count;
+
+    let mut covariance = Matrix3::zeros();
+    let mut source_variance = 0.0;
+    for (s, t) in source.iter().zip(target) {
+        let (s, t) = (s - source_mean, t - target_mean);
+        covariance += t * s.transpose();
+        source_variance += s.norm_squared();
+    }
+    if source_variance <= f64::EPSILON {
+        return None;
+    }
+
+    let svd = covariance.svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    // avoid reflections
+    let sign = (u * v_t).determinant().signum();
+    let correction = Vector3::new(1.0, 1.0, sign);
+    let rotation = u * Matrix3::from_diagonal(&correction) * v_t;
+    let scale = svd.singular_values.dot(&correction) / source_variance;
+    if !scale.is_finite() || scale <= 0.0 {
+        return None;
+    }
+    let translation = target_mean - scale * rotation * source_mean;
+    Some((scale, rotation, translation))
+}
+
+/// Estimate the head pose by fitting a 3D face model to the projected face
+/// landmarks with OpenCV's `solve_pnp`, which accounts for full perspective.
+/// * Args:
+///     - face_landmarks (`&[Landmark]`): Normalized face landmarks returned by `FaceLandmark`.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///     - camera (`Option<CameraIntrinsics>`): Camera intrinsics; approximated from the
+///       image size if `None`.
+///     - model_points (`&[(usize, [f64; 3])]`): Pairs of landmark index and 3D model
+///       point (`x` right, `y` up, `z` towards the camera); at least 4 points.
+///
+/// * Returns:
+///     - (`HeadPose`) Euler angles, rotation matrix and translation of the head.
+#[cfg(feature = "opencv")]
+pub fn estimate_head_pose_pnp(
+    face_landmarks: &[Landmark], image_size: (i32, i32), camera: Option<CameraIntrinsics>,
+    model_points: &[(usize, [f64; 3])],
+) -> Result<HeadPose, Error> {
+    if face_landmarks.len() < NUM_FACE_LANDMARKS {
+        return Err(Error::msg("unexpected number of items in face_landmarks"));
+    }
+    if model_points.len() < 4 {
+        return Err(Error::msg("model_points must contain at least 4 items"));
+    }
+
+    let camera = camera.unwrap_or_else(|| CameraIntrinsics::from_image_size(image_size));
+    let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+
+    let mut object_points = Vector::<Point3f>::new();
+    let mut image_points = Vector::<Point2f>::new();
+    for &(index, [x, y, z]) in model_points {
+        let landmark = face_landmarks
+            .get(index)
+            .ok_or(Error::msg(format!("invalid landmark index: {:?}", index)))?;
+        // model to camera convention (y down, z away from the camera)
+        object_points.push(Point3f::new(x as f32, -y as f32, -z as f32));
+        image_points.push(Point2f::new((landmark.x * width) as f32, (landmark.y * height) as f32));
+    }
+
+    let camera_matrix =
+        Mat::from_slice_2d(&[[camera.fx, 0.0, camera.cx], [0.0, camera.fy, camera.cy], [0.0, 0.0, 1.0]])?;
+    let mut rvec = Mat::default();
+    let mut tvec = Mat::default();
+    let solved = solve_pnp(
+        &object_points,
+        &image_points,
+        &camera_matrix,
+        &Mat::default(),
+        &mut rvec,
+        &mut tvec,
+        false,
+        SOLVEPNP_ITERATIVE,
+    )?;
+    if !solved {
+        return Err(Error::msg("failed to solve the head pose"));
+    }
+
+    let mut rotation_matrix = Mat::default();
+    rodrigues(&rvec, &mut rotation_matrix, &mut Mat::default())?;
+
+    // include the model to camera convention change in the rotation
+    let flip = [1.0, -1.0, -1.0];
+    let mut rotation = [[0.0; 3]; 3];
+    for (i, row) in rotation.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = *rotation_matrix.at_2d::<f64>(i as i32, j as i32)? * flip[j];
+        }
+    }
+    let translation = [*tvec.at::<f64>(0)?, *tvec.at::<f64>(1)?, *tvec.at::<f64>(2)?];
+
+    Ok(HeadPose::new(rotation, translation))
+}
+
+/// Convert a head pose to render data showing the model axes at the nose tip.
+/// The `x` (red), `y` (green) and `z` (blue) axes point to the right, up and
+/// out of the face respectively.
+/// * Args:
+///     - pose (`&HeadPose`): Head pose returned by `estimate_head_pose`.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///     - camera (`Option<CameraIntrinsics>`): Camera intrinsics used to estimate the pose.
+///     - axis_length (`f64`): Length of the axes in model units (e.g. millimetres).
+///     - thickness (`f64`): Width of the lines in viewport units (e.g. pixels).
+///     - output (`Option<Vec<Annotation>>`): Optional list of render annotations to add the items to.
+///
+/// * Returns:
+///     - `Vec<Annotation>`: List of render annotations with normalized positions.
+pub fn head_pose_to_render_data(
+    pose: &HeadPose, image_size: (i32, i32), camera: Option<CameraIntrinsics>, axis_length: f64, thickness: f64,
+    output: Option<Vec<Annotation>>,
+) -> Vec<Annotation> {
+    let camera = camera.unwrap_or_else(|| CameraIntrinsics::from_image_size(image_size));
+    let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+    let project = |point: [f64; 3]| {
+        let (x, y) = camera.project(pose.transform(point));
+        (x / width, y / height)
+    };
+
+    let origin = project([0.0, 0.0, 0.0]);
+    let axes: [([f64; 3], Color); 3] = [
+        ([axis_length, 0.0, 0.0], Colors::RED),
+        ([0.0, axis_length, 0.0], Colors::GREEN),
+        ([0.0, 0.0, axis_length], Colors::BLUE),
+    ];
+
+    let mut output = output.unwrap_or_default();
+    for (axis, color) in axes {
+        let end = project(axis);
+        let line = Line::new(origin.0, origin.1, end.0, end.1, false);
+        output.push(Annotation::new(vec![AnnotationData::Line(line)], true, thickness, color));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Rotation3;
+
+    /// Face landmarks of `HEAD_MODEL_POINTS` seen with a known pose.
+    fn project_model(pose: &HeadPose, image_size: (i32, i32), camera: &CameraIntrinsics) -> Vec<Landmark> {
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        let mut landmarks = vec![Landmark::new(0.0, 0.0, 0.0); NUM_FACE_LANDMARKS];
+        for (index, point) in HEAD_MODEL_POINTS {
+            let point = pose.transform(point);
+            let (x, y) = camera.project(point);
+            // depth relative to the nose tip in the scale of `x`
+            let z = (point[2] - pose.translation[2]) * camera.fx / pose.translation[2];
+            landmarks[index] = Landmark::new(x / width, y / height, z / width);
+        }
+        landmarks
+    }
+
+    #[test]
+    fn test_head_pose() {
+        let image_size = (640, 480);
+        let camera = CameraIntrinsics::from_image_size(image_size);
+
+        for (yaw, pitch, roll) in [(0.0, 0.0, 0.0), (25.0, -10.0, 5.0), (-30.0, 15.0, -12.0)] {
+            // `HeadPose::new` decomposes the face rotation as roll * yaw * pitch
+            let face = Rotation3::from_euler_angles(f64::to_radians(pitch), f64::to_radians(yaw), f64::to_radians(roll));
+            let mut rotation = [[0.0; 3]; 3];
+            for (i, row) in rotation.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    *value = if j == 0 { face[(i, j)] } else { -face[(i, j)] };
+                }
+            }
+            let expected = HeadPose::new(rotation, [30.0, -20.0, 600.0]);
+            assert!((expected.yaw - yaw).abs() < 1e-9);
+
+            let landmarks = project_model(&expected, image_size, &camera);
+            let pose = estimate_head_pose(&landmarks, image_size, None).unwrap();
+            assert!((pose.yaw - yaw).abs() < 2.0, "yaw {} != {}", pose.yaw, yaw);
+            assert!((pose.pitch - pitch).abs() < 2.0, "pitch {} != {}", pose.pitch, pitch);
+            assert!((pose.roll - roll).abs() < 2.0, "roll {} != {}", pose.roll, roll);
+            for (estimated, expected) in pose.translation.iter().zip(expected.translation) {
+                assert!((estimated - expected).abs() < 10.0, "translation {:?}", pose.translation);
+            }
+        }
+
+        assert!(estimate_head_pose(&[], image_size, None).is_err());
+        let pose = HeadPose::new([[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]], [0.0, 0.0, 600.0]);
+        let annotations = head_pose_to_render_data(&pose, image_size, None, 50.0, 2.0, None);
+        assert_eq!(annotations.len(), 3);
+    }
+
+    #[cfg(feature = "opencv")]
+    #[test]
+    fn test_head_pose_image() {
+        use crate::face_detection_lite::face_detection::{FaceDetection, FaceDetectionModel};
+        use crate::face_detection_lite::face_landmark::{face_detection_to_roi, FaceLandmark};
+        use crate::face_detection_lite::utils::convert_image_to_mat;
+
+        let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
+        let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
+        let image = convert_image_to_mat(im_bytes).unwrap();
+        let img_shape = image.size().unwrap();
+        let image_size = (img_shape.width, img_shape.height);
+
+        let faces = face_detection.infer(&image, None).unwrap();
+        let face_roi = face_detection_to_roi(faces[0].clone(), image_size, None).unwrap();
+        let face_landmark = FaceLandmark::new(None).unwrap();
+        let lmks = face_landmark.infer(&image, Some(face_roi)).unwrap();
+
+        // the test image shows a frontal face
+        let pose = estimate_head_pose(&lmks, image_size, None).unwrap();
+        assert!(pose.yaw.abs() < 15.0 && pose.pitch.abs() < 20.0 && pose.roll.abs() < 10.0, "{:?}", pose);
+        assert!(pose.translation[2] > 0.0);
+
+        let pnp = estimate_head_pose_pnp(&lmks, image_size, None, &HEAD_MODEL_POINTS).unwrap();
+        assert!((pnp.yaw - pose.yaw).abs() < 10.0 && (pnp.roll - pose.roll).abs() < 10.0, "{:?}", pnp);
+    }
+}
@@ -0,0 +1,233 @@
+#[cfg(feature = "opencv")]
+use crate::face_detection_lite::transform::image_to_tensor_into;
+use crate::face_detection_lite::transform::{
+    allocate_tensor, pixels_to_tensor_into, rgb_image_to_tensor_into, PixelBuffer,
+};
+use crate::face_detection_lite::types::{ImageTensor, Rect};
+use anyhow::Error;
+use image::{DynamicImage, RgbImage};
+#[cfg(feature = "opencv")]
+use opencv::core::{Mat, MatTraitConst};
+
+/// Image accepted by the inference APIs.
+///
+/// Implemented for OpenCV matrices (with the `opencv` feature),
+/// `image::DynamicImage`, `image::RgbImage` and raw pixel buffers (`RawImage`).
+/// OpenCV matrices are expected to be RGB.
+pub trait ImageInput {
+    /// Return the size of the image as `(width, height)` in pixels.
+    fn image_size(&self) -> Result<(i32, i32), Error>;
+
+    /// Convert a region of the image into a model input tensor written
+    /// straight into `output`, e.g. the input tensor of a model interpreter.
+    /// * Args:
+    ///     - output (`&mut [f32]`): Buffer of `width * height * 3` values receiving the
+    ///       tensor data in `(height, width, 3)` order.
+    ///     - roi (`Option<Rect>`): Location within the image where to convert; the entire
+    ///       image if `None`. Rotation is supported.
+    ///     - output_size (`Option<(i32, i32)>`): Tuple of `(width, height)` of the output
+    ///       tensor; defaults to the ROI size if `None`.
+    ///     - keep_aspect_ratio (bool): Keep the ROI aspect ratio and apply letterboxing.
+    ///     - output_range (`(f64, f64)`): Tuple of `(min_val, max_val)` of the output values.
+    ///     - flip_horizontal (`bool`): Flip the resulting image horizontally.
+    ///
+    /// * Returns:
+    ///     - (`(f64, f64, f64, f64)`): Padding for reversing letterboxing.
+    fn write_tensor(
+        &self, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool,
+        output_range: (f64, f64), flip_horizontal: bool,
+    ) -> Result<(f64, f64, f64, f64), Error>;
+
+    /// Convert a region of the image into a newly allocated tensor; see `write_tensor`.
+    /// * Returns:
+    ///     - (`ImageTensor`): Tensor data of shape `(height, width, 3)`, padding for
+    ///       reversing letterboxing and original image dimensions.
+    fn to_tensor(
+        &self, roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool, output_range: (f64, f64),
+        flip_horizontal: bool,
+    ) -> Result<ImageTensor, Error> {
+        allocate_tensor(self.image_size()?, roi, output_size, |output| {
+            self.write_tensor(output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+        })
+    }
+}
+
+#[cfg(feature = "opencv")]
+impl ImageInput for Mat {
+    fn image_size(&self) -> Result<(i32, i32), Error> {
+        let size = self.size()?;
+        Ok((size.width, size.height))
+    }
+
+    fn write_tensor(
+        &self, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool,
+        output_range: (f64, f64), flip_horizontal: bool,
+    ) -> Result<(f64, f64, f64, f64), Error> {
+        image_to_tensor_into(self, output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+    }
+}
+
+impl ImageInput for DynamicImage {
+    fn image_size(&self) -> Result<(i32, i32), Error> {
+        Ok((self.width() as i32, self.height() as i32))
+    }
+
+    fn write_tensor(
+        &self, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool,
+        output_range: (f64, f64), flip_horizontal: bool,
+    ) -> Result<(f64, f64, f64, f64), Error> {
+        match self {
+            DynamicImage::ImageRgb8(image) => {
+                image.write_tensor(output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+            }
+            _ => self
+                .to_rgb8()
+                .write_tensor(output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal),
+        }
+    }
+}
+
+impl ImageInput for RgbImage {
+    fn image_size(&self) -> Result<(i32, i32), Error> {
+        Ok((self.width() as i32, self.height() as i32))
+    }
+
+    fn write_tensor(
+        &self, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool,
+        output_range: (f64, f64), flip_horizontal: bool,
+    ) -> Result<(f64, f64, f64, f64), Error> {
+        rgb_image_to_tensor_into(self, output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+    }
+}
+
+/// Channel order of the pixels of a `RawImage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+}
+
+impl PixelFormat {
+    /// Byte offsets of the red, green and blue channels within a pixel.
+    fn channels(&self) -> [usize; 3] {
+        match self {
+            PixelFormat::Rgb => [0, 1, 2],
+            PixelFormat::Bgr => [2, 1, 0],
+        }
+    }
+}
+
+/// Borrowed 8-bit, 3-channel image, e.g. a camera frame or a buffer shared
+/// with another imaging library. Rows may be padded (`stride > 3 * width`).
+#[derive(Debug, Clone, Copy)]
+pub struct RawImage<'a> {
+    data: &'a [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl<'a> RawImage<'a> {
+    /// Wrap a pixel buffer.
+    /// * Args:
+    ///     - data (`&[u8]`): Pixel data, row by row.
+    ///     - width (`u32`): Image width in pixels.
+    ///     - height (`u32`): Image height in pixels.
+    ///     - stride (`Option<usize>`): Number of bytes between the starts of two consecutive
+    ///       rows; `3 * width` if `None`.
+    ///     - format (`PixelFormat`): Channel order of the pixels.
+    ///
+    /// * Returns:
+    ///     - (`RawImage`) The image, or an error if the buffer is too small for the given size.
+    pub fn new(
+        data: &'a [u8], width: u32, height: u32, stride: Option<usize>, format: PixelFormat,
+    ) -> Result<Self, Error> {
+        let row_size = width as usize * 3;
+        let stride = stride.unwrap_or(row_size);
+        if stride < row_size {
+            return Err(Error::msg(format!("stride {} is smaller than the row size {}", stride, row_size)));
+        }
+        let required = if height == 0 { 0 } else { stride * (height as usize - 1) + row_size };
+        if data.len() < required {
+            return Err(Error::msg(format!(
+                "buffer of {} bytes is too small for a {}x{} image with stride {}",
+                data.len(),
+                width,
+                height,
+                stride
+            )));
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            stride,
+            format,
+        })
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+}
+
+impl ImageInput for RawImage<'_> {
+    fn image_size(&self) -> Result<(i32, i32), Error> {
+        Ok((self.width as i32, self.height as i32))
+    }
+
+    fn write_tensor(
+        &self, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool,
+        output_range: (f64, f64), flip_horizontal: bool,
+    ) -> Result<(f64, f64, f64, f64), Error> {
+        let pixels = PixelBuffer {
+            data: self.data,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            channels: self.format.channels(),
+        };
+        pixels_to_tensor_into(&pixels, output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_raw_image_input() {
+        let image = RgbImage::from_fn(5, 4, |x, y| Rgb([(x * 40) as u8, (y * 60) as u8, (x * y * 10) as u8]));
+        let roi = Some(Rect::new(0.5, 0.5, 0.8, 0.6, 0.3, true));
+        let expected = image.to_tensor(roi, Some((6, 6)), true, (-1., 1.), false).unwrap();
+
+        // BGR rows padded to 20 bytes
+        let stride = 20;
+        let mut data = vec![255u8; stride * 4];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let index = y as usize * stride + x as usize * 3;
+            data[index..index + 3].copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+        let raw = RawImage::new(&data, 5, 4, Some(stride), PixelFormat::Bgr).unwrap();
+        assert_eq!(raw.image_size().unwrap(), (5, 4));
+        let tensor = raw.to_tensor(roi, Some((6, 6)), true, (-1., 1.), false).unwrap();
+        assert_eq!(tensor.tensor_data, expected.tensor_data);
+        assert_eq!(tensor.padding, expected.padding);
+
+        let dynamic = DynamicImage::ImageRgb8(image).to_tensor(roi, Some((6, 6)), true, (-1., 1.), false).unwrap();
+        assert_eq!(dynamic.tensor_data, expected.tensor_data);
+
+        assert!(RawImage::new(&data, 5, 4, Some(14), PixelFormat::Rgb).is_err());
+        assert!(RawImage::new(&data[..stride * 3 + 14], 5, 4, Some(stride), PixelFormat::Rgb).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::face_detection_lite::quantization::{read_tensor_quantization, QuantizationParams};
+use anyhow::Error;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tflite::context::ElementKind;
+use tflite::ops::builtin::BuiltinOpResolver;
+use tflite::{FlatBufferModel, Interpreter, InterpreterBuilder};
+
+/// Interpreter type owned by the model wrappers.
+pub type ModelInterpreter = Interpreter<'static, BuiltinOpResolver>;
+
+/// Pool of long-lived TFLite interpreters sharing a single model.
+///
+/// Each interpreter is built and has its tensors allocated exactly once, so
+/// repeated inference only copies the input data and invokes the model.
+///
+/// A TFLite interpreter cannot be invoked concurrently, so every interpreter
+/// is guarded by a `Mutex`. Calls from multiple threads are spread across the
+/// pool in a round-robin fashion; if all interpreters are busy the caller
+/// blocks until one becomes available. Use a pool size equal to the number
+/// of threads that run inference concurrently to avoid any contention.
+///
+/// Float32 as well as uint8/int8 quantized models are supported; the
+/// quantization parameters of the model tensors are read when the model is
+/// loaded (see `PooledInterpreter`).
+pub struct InterpreterPool {
+    model: Arc<FlatBufferModel>,
+    quantization: Vec<Option<QuantizationParams>>,
+    interpreters: Vec<Mutex<ModelInterpreter>>,
+    next: AtomicUsize,
+}
+
+impl InterpreterPool {
+    /// Create a new pool with `size` interpreters for the content of a `.tflite` file.
+    pub fn new(model: Vec<u8>, size: usize) -> Result<Self, Error> {
+        let quantization = read_tensor_quantization(&model)?;
+        let mut pool = InterpreterPool {
+            model: Arc::new(FlatBufferModel::build_from_buffer(model)?),
+            quantization,
+            interpreters: Vec::new(),
+            next: AtomicUsize::new(0),
+        };
+        pool.resize(size)?;
+        Ok(pool)
+    }
+
+    /// Create a new pool with `size` interpreters for a `.tflite` file.
+    pub fn from_file(path: impl AsRef<Path>, size: usize) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let model = std::fs::read(path)
+            .map_err(|e| Error::msg(format!("failed to read model {}: {}", path.display(), e)))?;
+        Self::new(model, size)
+    }
+
+    /// Number of interpreters in the pool.
+    pub fn len(&self) -> usize {
+        self.interpreters.len()
+    }
+
+    /// Grow or shrink the pool to `size` interpreters.
+    pub fn resize(&mut self, size: usize) -> Result<(), Error> {
+        if size == 0 {
+            return Err(Error::msg("interpreter pool size must be at least 1"));
+        }
+        self.interpreters.truncate(size);
+        while self.interpreters.len() < size {
+            let interpreter = build_interpreter(&self.model)?;
+            self.interpreters.push(Mutex::new(interpreter));
+        }
+        Ok(())
+    }
+
+    /// Borrow an interpreter for exclusive use.
+    /// Idle interpreters are preferred; the call only blocks when every
+    /// interpreter of the pool is currently in use.
+    pub fn acquire(&self) -> Result<PooledInterpreter<'_>, Error> {
+        let count = self.interpreters.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % count;
+
+        let mut interpreter = None;
+        for offset in 0..count {
+            if let Ok(guard) = self.interpreters[(start + offset) % count].try_lock() {
+                interpreter = Some(guard);
+                break;
+            }
+        }
+        let interpreter = match interpreter {
+            Some(interpreter) => interpreter,
+            None => self.interpreters[start]
+                .lock()
+                .map_err(|_| Error::msg("interpreter lock poisoned"))?,
+        };
+
+        Ok(PooledInterpreter {
+            interpreter,
+            quantization: &self.quantization,
+        })
+    }
+}
+
+/// Interpreter borrowed from an `InterpreterPool`.
+///
+/// Dereferences to the TFLite interpreter and additionally converts between
+/// real values and the element type of quantized input and output tensors.
+pub struct PooledInterpreter<'a> {
+    interpreter: MutexGuard<'a, ModelInterpreter>,
+    quantization: &'a [Option<QuantizationParams>],
+}
+
+impl PooledInterpreter<'_> {
+    /// Resize the batch dimension of the first model input to `batch_size`
+    /// and reallocate the tensors.
+    /// Returns `false` and keeps the current batch size if the model does not
+    /// support the requested batch size, e.g. because its batch dimension is fixed
+    /// or folded into the outputs.
+    pub fn set_batch_size(&mut self, batch_size: usize) -> Result<bool, Error> {
+        let input_index = self.interpreter.inputs()[0];
+        let input_info = self
+            .interpreter
+            .tensor_info(input_index)
+            .ok_or(Error::msg("missing model input info"))?;
+        let current = input_info.dims[0];
+        if current == batch_size {
+            return Ok(true);
+        }
+
+        let resize = |interpreter: &mut ModelInterpreter, batch: usize| -> Result<(), Error> {
+            let mut dims: Vec<i32> = input_info.dims.iter().map(|&d| d as i32).collect();
+            dims[0] = batch as i32;
+            interpreter.resize_input_tensor(input_index, &dims)?;
+            interpreter.allocate_tensors()?;
+            Ok(())
+        };
+
+        let batched_outputs = resize(&mut self.interpreter, batch_size).is_ok()
+            && self.interpreter.outputs().iter().all(|&index| {
+                self.interpreter
+                    .tensor_info(index)
+                    .is_some_and(|info| info.dims.first() == Some(&batch_size))
+            });
+        if !batched_outputs {
+            resize(&mut self.interpreter, current)?;
+        }
+        Ok(batched_outputs)
+    }
+
+    /// Fill an input tensor with real values produced by `write`.
+    /// Float32 tensors are written in place; values for uint8/int8 tensors are
+    /// quantized with the tensor's quantization parameters.
+    pub fn write_input<T>(
+        &mut self, index: i32, write: impl FnOnce(&mut [f32]) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let info = self
+            .interpreter
+            .tensor_info(index)
+            .ok_or(Error::msg(format!("missing info of tensor {}", index)))?;
+        match info.element_kind {
+            ElementKind::kTfLiteFloat32 => write(self.interpreter.tensor_data_mut(index)?),
+            ElementKind::kTfLiteUInt8 | ElementKind::kTfLiteInt8 => {
+                let params = self.params(index, &info.name)?;
+                let mut values = vec![0f32; info.dims.iter().product()];
+                let result = write(&mut values)?;
+
+                let is_signed = matches!(info.element_kind, ElementKind::kTfLiteInt8);
+                let buffer = self
+                    .interpreter
+                    .tensor_buffer_mut(index)
+                    .ok_or(Error::msg(format!("missing data of tensor '{}'", info.name)))?;
+                if buffer.len() != values.len() {
+                    return Err(Error::msg(format!("unexpected size of tensor '{}'", info.name)));
+                }
+                for (quantized, &value) in buffer.iter_mut().zip(&values) {
+                    *quantized = if is_signed {
+                        params.quantize(value, i8::MIN as i32, i8::MAX as i32) as i8 as u8
+                    } else {
+                        params.quantize(value, u8::MIN as i32, u8::MAX as i32) as u8
+                    };
+                }
+                Ok(result)
+            }
+            kind => Err(unsupported_type(kind, &info.name)),
+        }
+    }
+
+    /// Return the content of an output tensor as real values.
+    /// Uint8/int8 tensors are dequantized with the tensor's quantization parameters.
+    pub fn read_output(&self, index: i32) -> Result<Vec<f32>, Error> {
+        let info = self
+            .interpreter
+            .tensor_info(index)
+            .ok_or(Error::msg(format!("missing info of tensor {}", index)))?;
+        match info.element_kind {
+            ElementKind::kTfLiteFloat32 => Ok(self.interpreter.tensor_data::<f32>(index)?.to_vec()),
+            ElementKind::kTfLiteUInt8 | ElementKind::kTfLiteInt8 => {
+                let params = self.params(index, &info.name)?;
+                let is_signed = matches!(info.element_kind, ElementKind::kTfLiteInt8);
+                let buffer = self
+                    .interpreter
+                    .tensor_buffer(index)
+                    .ok_or(Error::msg(format!("missing data of tensor '{}'", info.name)))?;
+                Ok(buffer
+                    .iter()
+                    .map(|&value| {
+                        let value = if is_signed { value as i8 as i32 } else { value as i32 };
+                        params.dequantize(value)
+                    })
+                    .collect())
+            }
+            kind => Err(unsupported_type(kind, &info.name)),
+        }
+    }
+
+    fn params(&self, index: i32, name: &str) -> Result<QuantizationParams, Error> {
+        self.quantization
+            .get(index as usize)
+            .copied()
+            .flatten()
+            .ok_or(Error::msg(format!("missing quantization parameters of tensor '{}'", name)))
+    }
+}
+
+impl Deref for PooledInterpreter<'_> {
+    type Target = ModelInterpreter;
+
+    fn deref(&self) -> &Self::Target {
+        &self.interpreter
+    }
+}
+
+impl DerefMut for PooledInterpreter<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.interpreter
+    }
+}
+
+/// Split the data of a batched input tensor into the data of every item.
+/// Fails if the data cannot be divided evenly into `count` items.
+pub fn batch_items(input: &mut [f32], count: usize) -> Result<std::slice::ChunksMut<'_, f32>, Error> {
+    if input.is_empty() || !input.len().is_multiple_of(count) {
+        return Err(Error::msg(format!(
+            "input tensor of {} values cannot be split into {} items",
+            input.len(),
+            count
+        )));
+    }
+    Ok(input.chunks_mut(input.len() / count))
+}
+
+fn unsupported_type(kind: ElementKind, name: &str) -> Error {
+    Error::msg(format!(
+        "unsupported type {:?} of tensor '{}'; only float32, uint8 and int8 tensors are supported",
+        kind, name
+    ))
+}
+
+/// Build an interpreter for the model and allocate its tensors.
+fn build_interpreter(model: &Arc<FlatBufferModel>) -> Result<ModelInterpreter, Error> {
+    let builder = InterpreterBuilder::new(model.clone(), BuiltinOpResolver::default())?;
+    let mut interpreter = builder.build()?;
+    interpreter.allocate_tensors()?;
+    Ok(interpreter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_items() {
+        let mut input = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let items: Vec<&mut [f32]> = batch_items(&mut input, 3).unwrap().collect();
+        assert_eq!(items, vec![&mut [0.0, 1.0], &mut [2.0, 3.0], &mut [4.0, 5.0]]);
+
+        assert!(batch_items(&mut input, 4).is_err());
+        assert!(batch_items(&mut input, 0).is_err());
+        assert!(batch_items(&mut [], 1).is_err());
+    }
+
+    #[test]
+    fn test_set_batch_size_fixed() {
+        // the reshape ops of the face detection model fix its batch size to 1
+        let pool = InterpreterPool::from_file("./models/face_detection_front.tflite", 1).unwrap();
+        let mut interpreter = pool.acquire().unwrap();
+        assert!(interpreter.set_batch_size(1).unwrap());
+        assert!(!interpreter.set_batch_size(2).unwrap());
+
+        // the interpreter keeps working with the original batch size
+        let input_index = interpreter.inputs()[0];
+        assert_eq!(interpreter.tensor_info(input_index).unwrap().dims[0], 1);
+        interpreter
+            .write_input(input_index, |input| {
+                input.fill(0.0);
+                Ok(())
+            })
+            .unwrap();
+        interpreter.invoke().unwrap();
+    }
+}
@@ -1,16 +1,18 @@
+#[cfg(feature = "embedded-models")]
+use crate::face_detection_lite::embedded_models;
+use crate::face_detection_lite::head_pose::CameraIntrinsics;
+use crate::face_detection_lite::input::ImageInput;
+use crate::face_detection_lite::interpreter::InterpreterPool;
 use crate::face_detection_lite::render::{
     landmarks_to_render_data, Annotation, AnnotationData, Color, Colors, Point, RectOrOval,
 };
 use crate::face_detection_lite::transform::{
-    bbox_from_landmarks, bbox_to_roi, image_to_tensor, project_landmarks, SizeMode,
+    bbox_from_landmarks, bbox_to_roi, project_landmarks, SizeMode,
 };
 use crate::face_detection_lite::types::{Landmark, Rect};
 use anyhow::Error;
-use ndarray::{Array4, Axis};
-use opencv::core::Mat;
+use ndarray::Array4;
 use std::path::PathBuf;
-use tflite::ops::builtin::BuiltinOpResolver;
-use tflite::{FlatBufferModel, InterpreterBuilder};
 
 /// Iris landmark detection model.
 ///
@@ -95,9 +97,37 @@ pub const RIGHT_EYE_TO_FACE_LANDMARK_INDEX: [i32; 71] = [
 ];
 
 /// 35mm camera sensor diagonal (36mm * 24mm)
-/// const SENSOR_DIAGONAL_35MM: f64 = 1872_f64.sqrt();
+const SENSOR_DIAGONAL_35MM: f64 = 43.266_615_305_567_87;
 /// average human iris size
-const IRIS_SIZE_IN_MM: f64 = 11.8;
+pub const IRIS_SIZE_IN_MM: f64 = 11.8;
+
+/// Focal length of the camera that took the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FocalLength {
+    /// Focal length in pixels; the principal point is the image center.
+    Pixels(f64),
+    /// 35mm equivalent focal length in mm, e.g. the EXIF `FocalLengthIn35mmFilm` tag.
+    Equivalent35mm(f64),
+    /// Calibrated camera intrinsics.
+    Intrinsics(CameraIntrinsics),
+}
+
+impl FocalLength {
+    /// Return the focal length and principal point in pixels.
+    /// * Args:
+    ///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+    pub fn to_pixels(&self, image_size: (i32, i32)) -> (f64, (f64, f64)) {
+        let (width, height) = (image_size.0 as f64, image_size.1 as f64);
+        let center = (width / 2., height / 2.);
+        match *self {
+            FocalLength::Pixels(focal_length) => (focal_length, center),
+            FocalLength::Equivalent35mm(focal_length) => {
+                (focal_length * width.hypot(height) / SENSOR_DIAGONAL_35MM, center)
+            }
+            FocalLength::Intrinsics(camera) => ((camera.fx + camera.fy) / 2., (camera.cx, camera.cy)),
+        }
+    }
+}
 
 #[repr(i32)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -109,23 +139,167 @@ pub enum IrisIndex {
     Bottom = 4,
 }
 
+/// Eye of a face, as used by the MediaPipe face landmarks: the left eye is
+/// the one around landmarks 33/133.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EyeSide {
+    Left,
+    Right,
+}
+
+/// Named segments of the 71 eye contour landmarks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EyeContourSegment {
+    /// Lower eyelid, including both eye corners
+    LowerContour,
+    /// Upper eyelid, excluding the eye corners
+    UpperContour,
+    LowerHalo2,
+    UpperHalo2,
+    LowerHalo3,
+    UpperHalo3,
+    /// Halo x4 upper contour or eyebrow inner contour
+    BrowInner,
+    LowerHalo5,
+    /// Halo x5 upper contour or eyebrow outer contour
+    BrowOuter,
+}
+
+impl EyeContourSegment {
+    /// Range of the segment in the eye contour landmarks.
+    pub fn range(&self) -> std::ops::Range<usize> {
+        match self {
+            EyeContourSegment::LowerContour => 0..9,
+            EyeContourSegment::UpperContour => 9..16,
+            EyeContourSegment::LowerHalo2 => 16..25,
+            EyeContourSegment::UpperHalo2 => 25..32,
+            EyeContourSegment::LowerHalo3 => 32..41,
+            EyeContourSegment::UpperHalo3 => 41..48,
+            EyeContourSegment::BrowInner => 48..54,
+            EyeContourSegment::LowerHalo5 => 54..63,
+            EyeContourSegment::BrowOuter => 63..71,
+        }
+    }
+}
+
 /// Iris detection results.
 /// contour data is 71 points defining the eye region
 /// iris data is 5 keypoints
+#[derive(Debug, Clone)]
 pub struct IrisResults {
     contour: Vec<Landmark>,
     iris: Vec<Landmark>,
+    side: Option<EyeSide>,
 }
 
 impl IrisResults {
-    pub fn new(contour: Vec<Landmark>, iris: Vec<Landmark>) -> Self {
-        Self { contour, iris }
+    /// Create iris results from 71 eye contour and 5 iris landmarks.
+    pub fn new(contour: Vec<Landmark>, iris: Vec<Landmark>) -> Result<Self, Error> {
+        if contour.len() != NUM_EYE_LANDMARKS as usize {
+            return Err(Error::msg("unexpected number of items in contour"));
+        }
+        if iris.len() != NUM_IRIS_LANDMARKS as usize {
+            return Err(Error::msg("unexpected number of items in iris"));
+        }
+        Ok(Self {
+            contour,
+            iris,
+            side: None,
+        })
+    }
+
+    /// Tag the results with the eye they belong to.
+    pub fn with_side(mut self, side: EyeSide) -> Self {
+        self.side = Some(side);
+        self
+    }
+
+    /// Eye the results belong to; set by `IrisLandmark::infer`.
+    pub fn side(&self) -> Option<EyeSide> {
+        self.side
+    }
+
+    /// All 71 eye contour landmarks.
+    pub fn contour(&self) -> &[Landmark] {
+        &self.contour
+    }
+
+    /// Landmarks of a named eye contour segment.
+    pub fn contour_segment(&self, segment: EyeContourSegment) -> &[Landmark] {
+        &self.contour[segment.range()]
+    }
+
+    /// All 5 iris landmarks, ordered by `IrisIndex`.
+    pub fn iris_landmarks(&self) -> &[Landmark] {
+        &self.iris
     }
 
     pub fn eyeball_contour(&self) -> Vec<Landmark> {
         let lmk = self.contour.clone();
         lmk[0..MAX_EYE_LANDMARK].to_vec()
     }
+
+    /// Return an iris keypoint.
+    pub fn iris(&self, index: IrisIndex) -> Landmark {
+        self.iris[index as usize]
+    }
+
+    pub fn iris_center(&self) -> Landmark {
+        self.iris(IrisIndex::Center)
+    }
+
+    /// Calculate the iris diameter in pixels.
+    /// * Args:
+    ///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+    pub fn iris_diameter(&self, image_size: (i32, i32)) -> f64 {
+        get_iris_diameter(&self.iris, image_size)
+    }
+
+    /// Estimate the distance between the camera and the iris in mm, assuming
+    /// an average iris diameter of `IRIS_SIZE_IN_MM`.
+    /// * Args:
+    ///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+    ///     - focal_length (`FocalLength`): Focal length of the camera.
+    pub fn iris_depth(&self, image_size: (i32, i32), focal_length: FocalLength) -> f64 {
+        let (focal_length_px, principal_point) = focal_length.to_pixels(image_size);
+        let iris_size_px = self.iris_diameter(image_size);
+        get_iris_depth(&self.iris, focal_length_px, iris_size_px, image_size, principal_point)
+    }
+
+    /// Estimate the position of the iris center in camera coordinates in mm
+    /// (`x` right, `y` down, `z` away from the camera).
+    /// * Args:
+    ///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+    ///     - focal_length (`FocalLength`): Focal length of the camera.
+    pub fn iris_position(&self, image_size: (i32, i32), focal_length: FocalLength) -> [f64; 3] {
+        let (focal_length_px, (cx, cy)) = focal_length.to_pixels(image_size);
+        let center = self.iris[IrisIndex::Center as usize];
+        let ray = [
+            center.x * image_size.0 as f64 - cx,
+            center.y * image_size.1 as f64 - cy,
+            focal_length_px,
+        ];
+        let norm = (ray[0].powi(2) + ray[1].powi(2) + ray[2].powi(2)).sqrt();
+        let depth = self.iris_depth(image_size, focal_length);
+        [ray[0] * depth / norm, ray[1] * depth / norm, ray[2] * depth / norm]
+    }
+}
+
+/// Estimate the interpupillary distance in mm from the iris results of both eyes.
+/// * Args:
+///     - left_eye (`&IrisResults`): Iris results of the left eye.
+///     - right_eye (`&IrisResults`): Iris results of the right eye.
+///     - image_size (`(i32, i32)`): Tuple of `(width, height)` of the image.
+///     - focal_length (`FocalLength`): Focal length of the camera.
+///
+/// * Returns:
+///     - (`f64`) Distance between the iris centers in mm.
+pub fn interpupillary_distance(
+    left_eye: &IrisResults, right_eye: &IrisResults, image_size: (i32, i32), focal_length: FocalLength,
+) -> f64 {
+    let left = left_eye.iris_position(image_size, focal_length);
+    let right = right_eye.iris_position(image_size, focal_length);
+    ((left[0] - right[0]).powi(2) + (left[1] - right[1]).powi(2) + (left[2] - right[2]).powi(2)).sqrt()
 }
 
 /// Model for iris landmark detection from the image of an eye.
@@ -134,8 +308,8 @@ impl IrisResults {
 /// The outputs of the model are 71 normalized eye contour landmarks and a
 /// separate list of 5 normalized iris landmarks.
 pub struct IrisLandmark {
-    model_path: PathBuf,
-    model: FlatBufferModel,
+    model_path: Option<PathBuf>,
+    interpreters: InterpreterPool,
 }
 
 impl IrisLandmark {
@@ -147,21 +321,46 @@ impl IrisLandmark {
         } else {
             model_path_buf = PathBuf::from("./models/iris_landmark.tflite");
         }
-        let model = FlatBufferModel::build_from_file(model_path_buf.clone())?;
+        let interpreters = InterpreterPool::from_file(&model_path_buf, 1)?;
 
         Ok(IrisLandmark {
-            model_path: model_path_buf,
-            model,
+            model_path: Some(model_path_buf),
+            interpreters,
         })
     }
 
-    pub fn infer(&self, image: &Mat, roi: Option<Rect>, is_right_eye: Option<bool>) -> Result<IrisResults, Error> {
+    /// Create the iris landmark model from the content of a `.tflite` file.
+    pub fn from_buffer(buffer: impl Into<Vec<u8>>) -> Result<IrisLandmark, Error> {
+        let interpreters = InterpreterPool::new(buffer.into(), 1)?;
+
+        Ok(IrisLandmark {
+            model_path: None,
+            interpreters,
+        })
+    }
+
+    /// Create the iris landmark model from the model bundled with the crate.
+    #[cfg(feature = "embedded-models")]
+    pub fn from_embedded() -> Result<IrisLandmark, Error> {
+        Self::from_buffer(embedded_models::IRIS_LANDMARK)
+    }
+
+    /// Set the number of interpreters used for iris inference.
+    /// Both eyes of a face can be processed in parallel with a pool size of 2.
+    pub fn with_pool_size(mut self, size: usize) -> Result<Self, Error> {
+        self.interpreters.resize(size)?;
+        Ok(self)
+    }
+
+    /// Number of interpreters in the pool.
+    pub fn pool_size(&self) -> usize {
+        self.interpreters.len()
+    }
+
+    pub fn infer(&self, image: &impl ImageInput, roi: Option<Rect>, is_right_eye: Option<bool>) -> Result<IrisResults, Error> {
         let is_right_eye = is_right_eye.unwrap_or(false);
 
-        let resolver = BuiltinOpResolver::default();
-        let builder = InterpreterBuilder::new(&self.model, &resolver)?;
-        let mut interpreter = builder.build()?;
-        interpreter.allocate_tensors()?;
+        let mut interpreter = self.interpreters.acquire()?;
 
         let input_details = interpreter.get_input_details()?;
         let output_details = interpreter.get_output_details()?;
@@ -185,22 +384,15 @@ impl IrisLandmark {
 
         let (height, width) = (input_shape[1], input_shape[2]);
 
-        let image_data =
-            image_to_tensor(image, roi, Some((width as i32, height as i32)), true, (0., 1.), is_right_eye)?;
-        let input_data = image_data
-            .tensor_data
-            .into_dimensionality::<ndarray::IxDyn>()
-            .unwrap()
-            .insert_axis(Axis(0));
 
-        // Infer model with input data
+        // Convert the image straight into the model input tensor and infer
         let inputs = interpreter.inputs().to_vec();
         let input_index = inputs[0];
-        let sub_tensor: Vec<f32> = input_data.into_iter().collect();
-        interpreter
-            .tensor_data_mut(input_index)?
-            .copy_from_slice(&sub_tensor);
+        let padding = interpreter.write_input(input_index, |input| {
+            image.write_tensor(input, roi, Some((width as i32, height as i32)), true, (0., 1.), is_right_eye)
+        })?;
         interpreter.invoke()?;
+        let image_size = image.image_size()?;
 
         // retrieve outputs
         let outputs = interpreter.outputs().to_vec();
@@ -215,23 +407,23 @@ impl IrisLandmark {
             .tensor_info(iris_landmarks_index)
             .ok_or(Error::msg("missing iris landmarks outputs info"))?;
 
-        let raw_eye_landmarks_s: &[f32] = interpreter.tensor_data(eye_landmarks_index).unwrap();
+        let raw_eye_landmarks_s = interpreter.read_output(eye_landmarks_index)?;
         let raw_eye_landmarks: Array4<f32> = Array4::from_shape_vec(
             (1, 1, eye_landmarks_info.dims[0], eye_landmarks_info.dims[1]),
-            raw_eye_landmarks_s.to_vec(),
+            raw_eye_landmarks_s,
         )?;
 
-        let iris_landmarks_s: &[f32] = interpreter.tensor_data(iris_landmarks_index).unwrap();
+        let iris_landmarks_s = interpreter.read_output(iris_landmarks_index)?;
         let raw_iris_landmarks: Array4<f32> = Array4::from_shape_vec(
             (1, 1, iris_landmarks_info.dims[0], iris_landmarks_info.dims[1]),
-            iris_landmarks_s.to_vec(),
+            iris_landmarks_s,
         )?;
 
         let eye_contour = project_landmarks(
             raw_eye_landmarks,
             (width as i32, height as i32),
-            image_data.original_size,
-            image_data.padding,
+            image_size,
+            padding,
             roi,
             is_right_eye,
         )?;
@@ -239,12 +431,57 @@ impl IrisLandmark {
         let iris_landmarks = project_landmarks(
             raw_iris_landmarks,
             (width as i32, height as i32),
-            image_data.original_size,
-            image_data.padding,
+            image_size,
+            padding,
             roi,
             is_right_eye,
         )?;
-        Ok(IrisResults::new(eye_contour, iris_landmarks))
+        let side = if is_right_eye { EyeSide::Right } else { EyeSide::Left };
+        Ok(IrisResults::new(eye_contour, iris_landmarks)?.with_side(side))
+    }
+
+    /// Detect the iris landmarks of both eyes of a face.
+    /// * Args:
+    ///     - image (`impl ImageInput`): OpenCV matrix or `DynamicImage`.
+    ///     - face_landmarks (`&[Landmark]`): Result of a `FaceLandmark` call on the same image.
+    ///
+    /// * Returns:
+    ///     - (`EyeResults`) Iris results of the left and right eye.
+    pub fn infer_eyes(&self, image: &impl ImageInput, face_landmarks: &[Landmark]) -> Result<EyeResults, Error> {
+        if face_landmarks.len() < NUM_FACE_LANDMARKS {
+            return Err(Error::msg("unexpected number of items in face_landmarks"));
+        }
+        let (left_eye_roi, right_eye_roi) = iris_roi_from_face_landmarks(face_landmarks.to_vec(), image.image_size()?)?;
+        let left = self.infer(image, Some(left_eye_roi), Some(false))?;
+        let right = self.infer(image, Some(right_eye_roi), Some(true))?;
+        Ok(EyeResults { left, right })
+    }
+}
+
+/// Iris detection results of both eyes of a face.
+#[derive(Debug, Clone)]
+pub struct EyeResults {
+    pub left: IrisResults,
+    pub right: IrisResults,
+}
+
+impl EyeResults {
+    /// Return the results of one eye.
+    pub fn eye(&self, side: EyeSide) -> &IrisResults {
+        match side {
+            EyeSide::Left => &self.left,
+            EyeSide::Right => &self.right,
+        }
+    }
+
+    /// Estimate the interpupillary distance in mm; see `interpupillary_distance`.
+    pub fn interpupillary_distance(&self, image_size: (i32, i32), focal_length: FocalLength) -> f64 {
+        interpupillary_distance(&self.left, &self.right, image_size, focal_length)
+    }
+
+    /// Refine face landmarks with the eye results; see `update_face_landmarks_with_iris_results`.
+    pub fn update_face_landmarks(&self, face_landmarks: Vec<Landmark>) -> Result<Vec<Landmark>, Error> {
+        update_face_landmarks_with_iris_results(face_landmarks, self.left.clone(), self.right.clone())
     }
 }
 
@@ -398,7 +635,7 @@ pub fn update_face_landmarks_with_iris_results(
 }
 
 /// Calculate the iris diameter in pixels
-fn get_iris_diameter(iris_landmarks: &Vec<Landmark>, image_size: (i32, i32)) -> f64 {
+fn get_iris_diameter(iris_landmarks: &[Landmark], image_size: (i32, i32)) -> f64 {
     let (width, height) = image_size;
 
     let get_landmark_depth = |a: Landmark, b: Landmark| -> f64 {
@@ -417,17 +654,18 @@ fn get_iris_diameter(iris_landmarks: &Vec<Landmark>, image_size: (i32, i32)) ->
     (iris_size_vert + iris_size_horiz) / 2.
 }
 
-/// Calculate iris depth in mm from landmarks and lens focal length in mm
+/// Calculate iris depth in mm from landmarks and lens focal length in pixels
 fn get_iris_depth(
-    iris_landmarks: Vec<Landmark>, focal_length_mm: f64, iris_size_px: f64, image_size: (i32, i32),
+    iris_landmarks: &[Landmark], focal_length_px: f64, iris_size_px: f64, image_size: (i32, i32),
+    principal_point: (f64, f64),
 ) -> f64 {
     let (width, height) = image_size;
     let center = iris_landmarks[IrisIndex::Center as usize];
-    let (x0, y0) = (width / 2, height / 2);
+    let (x0, y0) = principal_point;
     let (x1, y1) = (center.x * width as f64, center.y * height as f64);
-    let y_square: f64 = (x0 as f64 - x1).powi(2) + (y0 as f64 - y1).powi(2);
+    let y_square: f64 = (x0 - x1).powi(2) + (y0 - y1).powi(2);
     let y = y_square.sqrt();
-    let x_square: f64 = focal_length_mm.powi(2) + y.powi(2);
+    let x_square: f64 = focal_length_px.powi(2) + y.powi(2);
     let x = x_square.sqrt();
     IRIS_SIZE_IN_MM * x / iris_size_px
 }
@@ -435,26 +673,88 @@ fn get_iris_depth(
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "opencv")]
     use crate::face_detection_lite::face_detection::{FaceDetection, FaceDetectionModel};
+    #[cfg(feature = "opencv")]
     use crate::face_detection_lite::face_landmark::{face_detection_to_roi, FaceLandmark};
+    #[cfg(feature = "opencv")]
     use crate::face_detection_lite::utils::convert_image_to_mat;
-    use opencv::core::MatTraitConst;
 
     #[test]
+    #[cfg(feature = "opencv")]
     fn test_face_landmark() {
         let face_detection = FaceDetection::new(FaceDetectionModel::BackCamera, None).unwrap();
         let im_bytes: &[u8] = include_bytes!("../../test_data/man.jpg");
         let image = convert_image_to_mat(im_bytes).unwrap();
-        let img_shape = image.size().unwrap();
+        let image_size = image.image_size().unwrap();
         let faces = face_detection.infer(&image, None).unwrap();
-        let face_roi = face_detection_to_roi(faces[0].clone(), (img_shape.width, img_shape.height), None).unwrap();
+        let face_roi = face_detection_to_roi(faces[0].clone(), image_size, None).unwrap();
         let face_landmark = FaceLandmark::new(None).unwrap();
         let lmks = face_landmark.infer(&image, Some(face_roi)).unwrap();
-        let (left_eye_roi, right_eye_roi) =
-            iris_roi_from_face_landmarks(lmks, (img_shape.width, img_shape.height)).unwrap();
+        let (left_eye_roi, right_eye_roi) = iris_roi_from_face_landmarks(lmks, image_size).unwrap();
         let iris_landmark = IrisLandmark::new(None).unwrap();
         iris_landmark
             .infer(&image, Some(right_eye_roi), Some(true))
             .unwrap();
     }
+
+    #[test]
+    fn test_iris_measurements() {
+        // irises with a diameter of 11.8 px, 31.5 px left and right of the image center
+        let iris_at = |x: f64| {
+            let radius = IRIS_SIZE_IN_MM / 2. / 1000.;
+            let iris = vec![
+                Landmark::new(x, 0.5, 0.),
+                Landmark::new(x - radius, 0.5, 0.),
+                Landmark::new(x, 0.5 - radius, 0.),
+                Landmark::new(x + radius, 0.5, 0.),
+                Landmark::new(x, 0.5 + radius, 0.),
+            ];
+            IrisResults::new(vec![Landmark::new(0., 0., 0.); NUM_EYE_LANDMARKS as usize], iris).unwrap()
+        };
+        let image_size = (1000, 1000);
+        let focal_length = FocalLength::Pixels(1000.);
+        let (left, right) = (iris_at(0.5 - 0.0315), iris_at(0.5 + 0.0315));
+
+        assert!((left.iris_diameter(image_size) - IRIS_SIZE_IN_MM).abs() < 1e-9);
+        let depth = iris_at(0.5).iris_depth(image_size, focal_length);
+        assert!((depth - 1000.).abs() < 1e-9);
+        let ipd = interpupillary_distance(&left, &right, image_size, focal_length);
+        assert!((ipd - 63.).abs() < 1e-9);
+
+        let (focal_length_px, _) = FocalLength::Equivalent35mm(SENSOR_DIAGONAL_35MM).to_pixels((3, 4));
+        assert!((focal_length_px - 5.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_iris_results_accessors() {
+        let contour: Vec<Landmark> = (0..NUM_EYE_LANDMARKS).map(|n| Landmark::new(n as f64, 0., 0.)).collect();
+        let iris: Vec<Landmark> = (0..NUM_IRIS_LANDMARKS).map(|n| Landmark::new(0., n as f64, 0.)).collect();
+        assert!(IrisResults::new(contour[..70].to_vec(), iris.clone()).is_err());
+        assert!(IrisResults::new(contour.clone(), iris[..4].to_vec()).is_err());
+        let results = IrisResults::new(contour, iris).unwrap().with_side(EyeSide::Right);
+
+        assert_eq!(results.side(), Some(EyeSide::Right));
+        assert_eq!(results.iris(IrisIndex::Bottom).y, 4.);
+        assert_eq!(results.iris_center().y, 0.);
+
+        let segments = [
+            EyeContourSegment::LowerContour,
+            EyeContourSegment::UpperContour,
+            EyeContourSegment::LowerHalo2,
+            EyeContourSegment::UpperHalo2,
+            EyeContourSegment::LowerHalo3,
+            EyeContourSegment::UpperHalo3,
+            EyeContourSegment::BrowInner,
+            EyeContourSegment::LowerHalo5,
+            EyeContourSegment::BrowOuter,
+        ];
+        let mut next = 0;
+        for segment in segments {
+            let landmarks = results.contour_segment(segment);
+            assert_eq!(landmarks[0].x, next as f64);
+            next += landmarks.len();
+        }
+        assert_eq!(next, results.contour().len());
+    }
 }
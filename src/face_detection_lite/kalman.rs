@@ -0,0 +1,119 @@
+use crate::face_detection_lite::types::BBox;
+use nalgebra::{SMatrix, SVector};
+
+type StateVector = SVector<f64, 8>;
+type StateMatrix = SMatrix<f64, 8, 8>;
+type MeasurementVector = SVector<f64, 4>;
+type MeasurementMatrix = SMatrix<f64, 4, 4>;
+type ObservationMatrix = SMatrix<f64, 4, 8>;
+
+/// Process noise of the position relative to the box height
+const STD_WEIGHT_POSITION: f64 = 1. / 20.;
+/// Process noise of the velocity relative to the box height
+const STD_WEIGHT_VELOCITY: f64 = 1. / 160.;
+
+/// Constant velocity Kalman filter for bounding boxes.
+///
+/// The state `(cx, cy, a, h, vx, vy, va, vh)` holds the box center, aspect
+/// ratio (width / height) and height, along with their velocities.
+/// Noise is modelled relative to the box height (as in DeepSORT), which makes
+/// the filter work with both normalized and absolute coordinates.
+#[derive(Debug, Clone)]
+pub struct KalmanBoxFilter {
+    mean: StateVector,
+    covariance: StateMatrix,
+}
+
+impl KalmanBoxFilter {
+    /// Initialize the filter from a first measurement.
+    pub fn new(bbox: &BBox) -> Self {
+        let measurement = to_measurement(bbox);
+        let mut mean = StateVector::zeros();
+        mean.fixed_rows_mut::<4>(0).copy_from(&measurement);
+
+        let h = measurement[3];
+        let std = [
+            2. * STD_WEIGHT_POSITION * h,
+            2. * STD_WEIGHT_POSITION * h,
+            1e-2,
+            2. * STD_WEIGHT_POSITION * h,
+            10. * STD_WEIGHT_VELOCITY * h,
+            10. * STD_WEIGHT_VELOCITY * h,
+            1e-5,
+            10. * STD_WEIGHT_VELOCITY * h,
+        ];
+        let covariance = StateMatrix::from_diagonal(&StateVector::from_iterator(std.iter().map(|s| s * s)));
+
+        Self { mean, covariance }
+    }
+
+    /// Advance the state by one time step.
+    pub fn predict(&mut self) {
+        let h = self.mean[3];
+        let std = [
+            STD_WEIGHT_POSITION * h,
+            STD_WEIGHT_POSITION * h,
+            1e-2,
+            STD_WEIGHT_POSITION * h,
+            STD_WEIGHT_VELOCITY * h,
+            STD_WEIGHT_VELOCITY * h,
+            1e-5,
+            STD_WEIGHT_VELOCITY * h,
+        ];
+        let motion_noise = StateMatrix::from_diagonal(&StateVector::from_iterator(std.iter().map(|s| s * s)));
+        let motion = motion_matrix();
+
+        self.mean = motion * self.mean;
+        self.covariance = motion * self.covariance * motion.transpose() + motion_noise;
+    }
+
+    /// Correct the state with a new measurement.
+    pub fn update(&mut self, bbox: &BBox) {
+        let h = self.mean[3];
+        let std = [STD_WEIGHT_POSITION * h, STD_WEIGHT_POSITION * h, 1e-1, STD_WEIGHT_POSITION * h];
+        let measurement_noise =
+            MeasurementMatrix::from_diagonal(&MeasurementVector::from_iterator(std.iter().map(|s| s * s)));
+        let observation = observation_matrix();
+
+        let projected_mean = observation * self.mean;
+        let projected_covariance = observation * self.covariance * observation.transpose() + measurement_noise;
+
+        let inverse = match projected_covariance.try_inverse() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        let kalman_gain = self.covariance * observation.transpose() * inverse;
+        let innovation = to_measurement(bbox) - projected_mean;
+
+        self.mean += kalman_gain * innovation;
+        self.covariance -= kalman_gain * projected_covariance * kalman_gain.transpose();
+    }
+
+    /// Return the current state estimate as a bounding box.
+    pub fn bbox(&self) -> BBox {
+        let (cx, cy, a, h) = (self.mean[0], self.mean[1], self.mean[2], self.mean[3]);
+        let w = a * h;
+        BBox::new(cx - w / 2., cy - h / 2., cx + w / 2., cy + h / 2.)
+    }
+}
+
+fn to_measurement(bbox: &BBox) -> MeasurementVector {
+    let (w, h) = (bbox.width(), bbox.height().max(f64::EPSILON));
+    MeasurementVector::new(bbox.xmin + w / 2., bbox.ymin + h / 2., w / h, h)
+}
+
+fn motion_matrix() -> StateMatrix {
+    let mut motion = StateMatrix::identity();
+    for i in 0..4 {
+        motion[(i, i + 4)] = 1.;
+    }
+    motion
+}
+
+fn observation_matrix() -> ObservationMatrix {
+    let mut observation = ObservationMatrix::zeros();
+    for i in 0..4 {
+        observation[(i, i)] = 1.;
+    }
+    observation
+}
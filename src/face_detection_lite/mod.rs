@@ -1,9 +1,26 @@
 pub mod types;
 mod transform;
 mod nms;
+mod interpreter;
+mod quantization;
+pub mod input;
+mod kalman;
+#[cfg(feature = "embedded-models")]
+pub mod embedded_models;
 pub mod face_detection;
 pub mod utils;
 pub mod render;
+pub mod render_backend;
 pub mod face_landmark;
 pub mod iris_landmark;
 pub mod face_embeddings;
+pub mod face_alignment;
+pub mod face_gallery;
+pub mod head_pose;
+pub mod blink;
+pub mod gaze;
+pub mod face_mesh;
+pub mod face_tracker;
+pub mod face_identity;
+
+pub use transform::SizeMode;
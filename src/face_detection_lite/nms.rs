@@ -2,7 +2,7 @@ use crate::face_detection_lite::types::{BBox, Detection};
 use ndarray::{Array, Array2, ArrayD, IxDyn, Zip};
 
 /// Return intersection-over-union similarity of two bounding boxes
-fn overlap_similarity(box1: &BBox, box2: &BBox) -> f64 {
+pub fn overlap_similarity(box1: &BBox, box2: &BBox) -> f64 {
     if let Some(intersection) = box1.intersect(box2) {
         let intersect_area = intersection.area();
         let denominator = box1.area() + box2.area() - intersect_area;
@@ -0,0 +1,214 @@
+use anyhow::Error;
+
+/// Affine quantization parameters of a tensor: `real = scale * (quantized - zero_point)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizationParams {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+impl QuantizationParams {
+    /// Quantize a real value into the range `[min, max]` of the quantized type.
+    pub fn quantize(&self, value: f32, min: i32, max: i32) -> i32 {
+        ((value / self.scale).round() as i32 + self.zero_point).clamp(min, max)
+    }
+
+    /// Convert a quantized value back into a real value.
+    pub fn dequantize(&self, value: i32) -> f32 {
+        self.scale * (value - self.zero_point) as f32
+    }
+}
+
+// Field indices of the TFLite flatbuffer schema tables
+const MODEL_SUBGRAPHS: usize = 2;
+const SUBGRAPH_TENSORS: usize = 0;
+const TENSOR_QUANTIZATION: usize = 4;
+const QUANTIZATION_SCALE: usize = 2;
+const QUANTIZATION_ZERO_POINT: usize = 3;
+
+/// Read the per-tensor quantization parameters of the main subgraph of a
+/// `.tflite` model, indexed like the interpreter tensors.
+/// Tensors without quantization parameters are `None`.
+pub fn read_tensor_quantization(model: &[u8]) -> Result<Vec<Option<QuantizationParams>>, Error> {
+    let buffer = FlatBuffer(model);
+    let root = buffer.offset(0)?;
+    let subgraphs = buffer
+        .field_offset(root, MODEL_SUBGRAPHS)?
+        .ok_or(Error::msg("model does not contain any subgraph"))?;
+    if buffer.u32(subgraphs)? == 0 {
+        return Err(Error::msg("model does not contain any subgraph"));
+    }
+    let subgraph = buffer.offset(subgraphs + 4)?;
+
+    let tensors = match buffer.field_offset(subgraph, SUBGRAPH_TENSORS)? {
+        Some(tensors) => tensors,
+        None => return Ok(Vec::new()),
+    };
+    let num_tensors = buffer.u32(tensors)? as usize;
+
+    let mut params = Vec::with_capacity(num_tensors);
+    for i in 0..num_tensors {
+        let tensor = buffer.offset(tensors + 4 + 4 * i)?;
+        let quantization = match buffer.field_offset(tensor, TENSOR_QUANTIZATION)? {
+            Some(quantization) => quantization,
+            None => {
+                params.push(None);
+                continue;
+            }
+        };
+
+        let scale = match buffer.field_offset(quantization, QUANTIZATION_SCALE)? {
+            Some(scale) if buffer.u32(scale)? > 0 => f32::from_bits(buffer.u32(scale + 4)?),
+            _ => {
+                params.push(None);
+                continue;
+            }
+        };
+        let zero_point = match buffer.field_offset(quantization, QUANTIZATION_ZERO_POINT)? {
+            Some(zero_point) if buffer.u32(zero_point)? > 0 => buffer.u64(zero_point + 4)? as i64 as i32,
+            _ => 0,
+        };
+        params.push(Some(QuantizationParams { scale, zero_point }));
+    }
+    Ok(params)
+}
+
+/// Minimal bounds-checked reader of little-endian flatbuffer tables.
+struct FlatBuffer<'a>(&'a [u8]);
+
+impl FlatBuffer<'_> {
+    fn bytes<const N: usize>(&self, pos: usize) -> Result<[u8; N], Error> {
+        self.0
+            .get(pos..pos + N)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(Error::msg("invalid model: unexpected end of flatbuffer"))
+    }
+
+    fn u16(&self, pos: usize) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.bytes(pos)?))
+    }
+
+    fn u32(&self, pos: usize) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(pos)?))
+    }
+
+    fn u64(&self, pos: usize) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.bytes(pos)?))
+    }
+
+    /// Follow the offset stored at `pos`.
+    fn offset(&self, pos: usize) -> Result<usize, Error> {
+        Ok(pos + self.u32(pos)? as usize)
+    }
+
+    /// Position of a field of the table at `table`, or `None` if the field is absent.
+    fn field(&self, table: usize, index: usize) -> Result<Option<usize>, Error> {
+        let vtable = table as i64 - i32::from_le_bytes(self.bytes(table)?) as i64;
+        if vtable < 0 {
+            return Err(Error::msg("invalid model: vtable out of bounds"));
+        }
+        let vtable = vtable as usize;
+        let entry = 4 + 2 * index;
+        if entry + 2 > self.u16(vtable)? as usize {
+            return Ok(None);
+        }
+        match self.u16(vtable + entry)? {
+            0 => Ok(None),
+            offset => Ok(Some(table + offset as usize)),
+        }
+    }
+
+    /// Follow the offset of a table, vector or string field.
+    fn field_offset(&self, table: usize, index: usize) -> Result<Option<usize>, Error> {
+        self.field(table, index)?.map(|pos| self.offset(pos)).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flatbuffer writer laying out tables with 4-byte offset fields only.
+    struct Builder(Vec<u8>);
+
+    impl Builder {
+        fn align(&mut self) {
+            self.0.resize(self.0.len().next_multiple_of(4), 0);
+        }
+
+        /// Write a table with `num_fields` fields of which `present` are set;
+        /// returns the position of the table and of its present fields.
+        fn table(&mut self, num_fields: usize, present: &[usize]) -> (usize, Vec<usize>) {
+            let vtable = self.0.len();
+            self.0.extend(((4 + 2 * num_fields) as u16).to_le_bytes());
+            self.0.extend(((4 + 4 * present.len()) as u16).to_le_bytes());
+            for field in 0..num_fields {
+                let offset = present.iter().position(|&p| p == field).map_or(0, |k| 4 + 4 * k);
+                self.0.extend((offset as u16).to_le_bytes());
+            }
+            self.align();
+            let table = self.0.len();
+            self.0.extend(((table - vtable) as i32).to_le_bytes());
+            let fields = (0..present.len()).map(|k| table + 4 + 4 * k).collect();
+            self.0.extend(vec![0; 4 * present.len()]);
+            (table, fields)
+        }
+
+        /// Write a vector of raw elements; returns the positions of the elements.
+        fn vector(&mut self, elements: &[&[u8]]) -> (usize, Vec<usize>) {
+            self.align();
+            let vector = self.0.len();
+            self.0.extend((elements.len() as u32).to_le_bytes());
+            let mut positions = Vec::new();
+            for element in elements {
+                positions.push(self.0.len());
+                self.0.extend(*element);
+            }
+            (vector, positions)
+        }
+
+        /// Point the offset at `pos` to `target`.
+        fn link(&mut self, pos: usize, target: usize) {
+            self.0[pos..pos + 4].copy_from_slice(&((target - pos) as u32).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_read_tensor_quantization() {
+        let mut builder = Builder(vec![0; 8]);
+        let (model, model_fields) = builder.table(5, &[MODEL_SUBGRAPHS]);
+        builder.link(0, model);
+        let (subgraphs, subgraph_offsets) = builder.vector(&[&[0; 4]]);
+        builder.link(model_fields[0], subgraphs);
+
+        let (subgraph, subgraph_fields) = builder.table(5, &[SUBGRAPH_TENSORS]);
+        builder.link(subgraph_offsets[0], subgraph);
+        let (tensors, tensor_offsets) = builder.vector(&[&[0; 4], &[0; 4]]);
+        builder.link(subgraph_fields[0], tensors);
+
+        // quantized tensor
+        let (tensor, tensor_fields) = builder.table(6, &[TENSOR_QUANTIZATION]);
+        builder.link(tensor_offsets[0], tensor);
+        let (quantization, quantization_fields) = builder.table(4, &[QUANTIZATION_SCALE, QUANTIZATION_ZERO_POINT]);
+        builder.link(tensor_fields[0], quantization);
+        let (scale, _) = builder.vector(&[&0.5f32.to_le_bytes()]);
+        builder.link(quantization_fields[0], scale);
+        let (zero_point, _) = builder.vector(&[&(-128i64).to_le_bytes()]);
+        builder.link(quantization_fields[1], zero_point);
+
+        // float tensor
+        let (tensor, _) = builder.table(6, &[]);
+        builder.link(tensor_offsets[1], tensor);
+
+        let params = read_tensor_quantization(&builder.0).unwrap();
+        assert_eq!(params.len(), 2);
+        let quantization = params[0].unwrap();
+        assert_eq!(quantization, QuantizationParams { scale: 0.5, zero_point: -128 });
+        assert_eq!(quantization.quantize(1.2, -128, 127), -126);
+        assert_eq!(quantization.quantize(1000., -128, 127), 127);
+        assert_eq!(quantization.dequantize(-126), 1.);
+        assert_eq!(params[1], None);
+
+        assert!(read_tensor_quantization(&builder.0[..builder.0.len() - 8]).is_err());
+    }
+}
@@ -1,7 +1,11 @@
 use crate::face_detection_lite::types::{Detection, Landmark};
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
 use anyhow::Error;
-use image::{DynamicImage, GenericImageView, Rgb, Rgba};
-use imageproc::drawing::{draw_filled_rect_mut, draw_hollow_rect, draw_hollow_rect_mut, draw_line_segment_mut};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::sync::OnceLock;
+
+/// Font of text annotations (DejaVu Sans Mono, see `assets/fonts/LICENSE`).
+pub const DEFAULT_FONT: &[u8] = include_bytes!("../../assets/fonts/DejaVuSansMono.ttf");
 
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
@@ -115,6 +119,10 @@ impl RectOrOval {
         (self.left, self.top, self.right, self.bottom)
     }
 
+    pub fn is_oval(&self) -> bool {
+        self.oval
+    }
+
     pub fn scaled(&self, factor: (f64, f64)) -> Self {
         let (sx, sy) = factor;
         RectOrOval {
@@ -146,13 +154,21 @@ impl FilledRectOrOval {
     }
 }
 
+/// Stroke pattern of a `Line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineStyle {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Line {
     x_start: f64,
     y_start: f64,
     x_end: f64,
     y_end: f64,
-    dashed: bool,
+    style: LineStyle,
 }
 
 impl Line {
@@ -162,10 +178,19 @@ impl Line {
             y_start,
             x_end,
             y_end,
-            dashed,
+            style: if dashed { LineStyle::Dashed } else { LineStyle::Solid },
         }
     }
 
+    pub fn with_style(mut self, style: LineStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn style(&self) -> LineStyle {
+        self.style
+    }
+
     pub fn as_tuple(&self) -> (f64, f64, f64, f64) {
         (self.x_start, self.y_start, self.x_end, self.y_end)
     }
@@ -178,17 +203,130 @@ impl Line {
             y_start: self.y_start * sy,
             x_end: self.x_end * sx,
             y_end: self.y_end * sy,
-            dashed: self.dashed,
+            style: self.style,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Point of a `Text` box placed at the text position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl TextAnchor {
+    /// Offset of the anchor from the top left corner of the box, relative to the box size.
+    fn offset(&self) -> (f64, f64) {
+        match self {
+            TextAnchor::TopLeft => (0.0, 0.0),
+            TextAnchor::Top => (0.5, 0.0),
+            TextAnchor::TopRight => (1.0, 0.0),
+            TextAnchor::Left => (0.0, 0.5),
+            TextAnchor::Center => (0.5, 0.5),
+            TextAnchor::Right => (1.0, 0.5),
+            TextAnchor::BottomLeft => (0.0, 1.0),
+            TextAnchor::Bottom => (0.5, 1.0),
+            TextAnchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// Single line of text, e.g. a score, track ID or identity name.
+/// The font size is in pixels and does not scale with normalized positions.
+#[derive(Debug, Clone)]
+pub struct Text {
+    x: f64,
+    y: f64,
+    text: String,
+    font_size: f64,
+    anchor: TextAnchor,
+    background: Option<Color>,
+}
+
+impl Text {
+    pub fn new(x: f64, y: f64, text: impl Into<String>, font_size: f64) -> Self {
+        Self {
+            x,
+            y,
+            text: text.into(),
+            font_size,
+            anchor: TextAnchor::TopLeft,
+            background: None,
+        }
+    }
+
+    pub fn with_anchor(mut self, anchor: TextAnchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// Fill a box behind the text with the given color.
+    pub fn with_background(mut self, background: Color) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn position(&self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+
+    pub fn font_size(&self) -> f64 {
+        self.font_size
+    }
+
+    pub fn anchor(&self) -> TextAnchor {
+        self.anchor
+    }
+
+    pub fn background(&self) -> Option<Color> {
+        self.background
+    }
+
+    pub fn scaled(&self, factor: (f64, f64)) -> Self {
+        let (sx, sy) = factor;
+        Text {
+            x: self.x * sx,
+            y: self.y * sy,
+            ..self.clone()
+        }
+    }
+
+    /// Return the box of the text as `(left, top, right, bottom)` in pixels,
+    /// with the position in pixels.
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        let font = default_font();
+        let font = font.as_scaled(PxScale::from(self.font_size as f32));
+        let (width, height) = (text_width(&font, &self.text) as f64, font.height() as f64);
+        let (ax, ay) = self.anchor.offset();
+        let (left, top) = ((self.x - ax * width).round(), (self.y - ay * height).round());
+        (left, top, left + width, top + height)
+    }
+
+    /// Return the padding of the background box around the text in pixels.
+    pub fn padding(&self) -> f64 {
+        (self.font_size * 0.15).round()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum AnnotationData {
     Point(Point),
     RectOrOval(RectOrOval),
     FilledRectOrOval(FilledRectOrOval),
     Line(Line),
+    Text(Text),
 }
 
 impl AnnotationData {
@@ -200,8 +338,21 @@ impl AnnotationData {
                 AnnotationData::FilledRectOrOval(filled_rect.scaled(factor))
             }
             AnnotationData::Line(line) => AnnotationData::Line(line.scaled(factor)),
+            AnnotationData::Text(text) => AnnotationData::Text(text.scaled(factor)),
         }
     }
+
+    /// Check that all positions and sizes are finite numbers.
+    fn is_finite(&self) -> bool {
+        let values = match self {
+            AnnotationData::Point(p) => vec![p.x, p.y],
+            AnnotationData::RectOrOval(r) => vec![r.left, r.top, r.right, r.bottom],
+            AnnotationData::FilledRectOrOval(f) => vec![f.rect.left, f.rect.top, f.rect.right, f.rect.bottom],
+            AnnotationData::Line(l) => vec![l.x_start, l.y_start, l.x_end, l.y_end],
+            AnnotationData::Text(t) => vec![t.x, t.y, t.font_size],
+        };
+        values.iter().all(|value| value.is_finite())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -312,6 +463,37 @@ pub fn detections_to_render_data(
     output
 }
 
+/// Convert the scores of detections to text labels above their bounding boxes.
+/// * Args:
+///     - detections (`&[Detection]`): Detections to label.
+///     - label_color (`Color`): Background color of the labels; the text is black or white,
+///       whichever reads better on it.
+///     - font_size (`f64`): Font size of the labels in pixels.
+///     - normalized_positions (`bool`): Flag indicating whether the detections contain normalised data (e.g. range [0,1]).
+///     - output (`Option<Vec<Annotation>>`): Optional list of render annotations to add the labels to.
+///
+/// * Returns:
+///     - `Vec<Annotation>`: List of render annotations with the labels.
+pub fn detection_labels_to_render_data(
+    detections: &[Detection], label_color: Color, font_size: f64, normalized_positions: bool,
+    output: Option<Vec<Annotation>>,
+) -> Vec<Annotation> {
+    let labels = detections
+        .iter()
+        .map(|detection| {
+            let bbox = detection.bbox();
+            let label = Text::new(bbox.xmin, bbox.ymin, format!("{:.2}", detection.score), font_size)
+                .with_anchor(TextAnchor::BottomLeft)
+                .with_background(label_color);
+            AnnotationData::Text(label)
+        })
+        .collect::<Vec<_>>();
+
+    let mut output = output.unwrap_or_default();
+    output.push(Annotation::new(labels, normalized_positions, 1.0, contrast_color(&label_color)));
+    output
+}
+
 pub fn landmarks_to_render_data(
     landmarks: Vec<Landmark>, landmark_connections: Vec<(i32, i32)>, landmark_color: Option<Color>,
     connection_color: Option<Color>, thickness: Option<f32>, normalized_positions: Option<bool>,
@@ -358,122 +540,633 @@ pub fn landmarks_to_render_data(
     }
 }
 
-pub fn render_to_image(annotations: &Vec<Annotation>, image: &DynamicImage, blend_mode: Option<bool>) -> DynamicImage {
-    let blend = blend_mode.unwrap_or(false);
-
-    let (width, height) = image.dimensions();
+/// `render_to_image` draws annotations onto a copy of an image.
+/// * Args:
+///     - annotations (`&[Annotation]`): Annotations to draw, in order.
+///     - image (`&DynamicImage`): Image to draw on.
+///     - blend_mode (`Option<bool>`): Composite the annotations over the image using the
+///       alpha of their colors (source-over), so semi-transparent fills and lines keep the
+///       underlying pixels visible. Otherwise colors, including their alpha, replace the
+///       pixels. Defaults to `false`.
+///
+/// * Returns:
+///     - (`DynamicImage`) RGBA image with the annotations.
+pub fn render_to_image(annotations: &[Annotation], image: &DynamicImage, blend_mode: Option<bool>) -> DynamicImage {
     let mut img = image.to_rgba8();
+    render(annotations, &mut ImageBackend::new(&mut img, blend_mode.unwrap_or(false)))
+        .expect("drawing onto an image does not fail");
+    DynamicImage::ImageRgba8(img)
+}
 
-    for annotation in annotations {
-        let scaled = if annotation.normalized_positions {
-            let scale_x = width as f64;
-            let scale_y = height as f64;
-            let scaled_thickness = annotation.thickness;
-            let scaled_color = annotation.color.clone();
-            let scaled_data = annotation
-                .data
-                .iter()
-                .map(|item| match item {
-                    AnnotationData::Point(p) => AnnotationData::Point(Point {
-                        x: p.x * scale_x,
-                        y: p.y * scale_y,
-                    }),
-                    AnnotationData::RectOrOval(r) => AnnotationData::RectOrOval(RectOrOval {
-                        left: r.left * scale_x,
-                        top: r.top * scale_y,
-                        right: r.right * scale_x,
-                        bottom: r.bottom * scale_y,
-                        oval: r.oval,
-                    }),
-                    AnnotationData::FilledRectOrOval(f) => AnnotationData::FilledRectOrOval(FilledRectOrOval {
-                        rect: RectOrOval {
-                            left: f.rect.left * scale_x,
-                            top: f.rect.top * scale_y,
-                            right: f.rect.right * scale_x,
-                            bottom: f.rect.bottom * scale_y,
-                            oval: f.rect.oval,
-                        },
-                        fill: f.fill.clone(),
-                    }),
-                    AnnotationData::Line(l) => AnnotationData::Line(Line {
-                        x_start: l.x_start * scale_x,
-                        y_start: l.y_start * scale_y,
-                        x_end: l.x_end * scale_x,
-                        y_end: l.y_end * scale_y,
-                        dashed: l.dashed,
-                    }),
-                })
-                .collect::<Vec<_>>();
+/// Target of `render`, e.g. an image, an OpenCV matrix or an SVG document.
+/// Positions, sizes and thicknesses passed to the backend are in pixels.
+pub trait RenderBackend {
+    /// Return the size of the canvas as `(width, height)` in pixels; normalized
+    /// annotations are scaled to it.
+    fn canvas_size(&self) -> (u32, u32);
 
-            Annotation {
-                data: scaled_data,
-                normalized_positions: false,
-                thickness: scaled_thickness,
-                color: scaled_color,
-            }
+    /// Draw a filled circle.
+    fn draw_point(&mut self, center: (f64, f64), radius: f64, color: Color) -> Result<(), Error>;
+
+    /// Draw a line of the given thickness in its style.
+    fn draw_line(&mut self, line: &Line, thickness: f64, color: Color) -> Result<(), Error>;
+
+    /// Draw the outline of a rectangle or oval with the given thickness, or fill it if `None`.
+    fn draw_rect_or_oval(&mut self, rect: &RectOrOval, thickness: Option<f64>, color: Color) -> Result<(), Error>;
+
+    /// Draw a line of text, on its background box if any.
+    fn draw_text(&mut self, text: &Text, color: Color) -> Result<(), Error>;
+}
+
+/// `render` draws annotations through a render backend.
+/// * Args:
+///     - annotations (`&[Annotation]`): Annotations to draw, in order.
+///     - backend (`&mut impl RenderBackend`): Canvas to draw on.
+///
+/// * Returns:
+///     - (`()`) Nothing, or the first error of the backend.
+pub fn render(annotations: &[Annotation], backend: &mut impl RenderBackend) -> Result<(), Error> {
+    let (width, height) = backend.canvas_size();
+    for annotation in annotations {
+        let annotation = if annotation.normalized_positions {
+            annotation.scaled((width as f64, height as f64))?
         } else {
             annotation.clone()
         };
 
-        let thickness = scaled.thickness as u32;
-        let color = scaled.color;
-
-        for item in scaled.data {
+        let thickness = annotation.thickness.max(1.0);
+        if !thickness.is_finite() {
+            continue;
+        }
+        let color = annotation.color;
+        // positions outside the canvas are clipped by the backends, non-finite ones are skipped
+        for item in annotation.data.iter().filter(|item| item.is_finite()) {
             match item {
-                AnnotationData::Point(p) => {
-                    let w = (thickness / 2).max(1);
-                    let x = p.x as u32;
-                    let y = p.y as u32;
-                    let rect = imageproc::rect::Rect::at((x - w) as i32, (y - w) as i32).of_size(w * 2, w * 2);
-                    draw_filled_rect_mut(
-                        &mut img,
-                        rect,
-                        Rgba::from([color.r as u8, color.g as u8, color.b as u8, color.a.unwrap_or(255) as u8]),
-                    );
-                }
-                AnnotationData::Line(l) => {
-                    let x_start = l.x_start as i32;
-                    let y_start = l.y_start as i32;
-                    let x_end = l.x_end as i32;
-                    let y_end = l.y_end as i32;
-                    draw_line_segment_mut(
-                        &mut img,
-                        (x_start as f32, y_start as f32),
-                        (x_end as f32, y_end as f32),
-                        Rgba::from([color.r as u8, color.g as u8, color.b as u8, color.a.unwrap_or(255) as u8]),
-                    );
-                }
-                AnnotationData::RectOrOval(r) => {
-                    let rect = imageproc::rect::Rect::at(r.left as i32, r.top as i32)
-                        .of_size((r.right - r.left) as u32, (r.bottom - r.top) as u32);
-                    if r.oval {
-                        draw_hollow_rect_mut(
-                            &mut img,
-                            rect,
-                            Rgba::from([color.r as u8, color.g as u8, color.b as u8, color.a.unwrap_or(255) as u8]),
-                        );
-                    } else {
-                        draw_hollow_rect_mut(
-                            &mut img,
-                            rect,
-                            Rgba::from([color.r as u8, color.g as u8, color.b as u8, color.a.unwrap_or(255) as u8]),
-                        );
-                    }
-                }
-                AnnotationData::FilledRectOrOval(f) => {
-                    let rect = imageproc::rect::Rect::at(f.rect.left as i32, f.rect.top as i32)
-                        .of_size((f.rect.right - f.rect.left) as u32, (f.rect.bottom - f.rect.top) as u32);
-                    let fill_color =
-                        Rgba::from([f.fill.r as u8, f.fill.g as u8, f.fill.b as u8, f.fill.a.unwrap_or(255) as u8]);
-                    if f.rect.oval {
-                        draw_filled_rect_mut(&mut img, rect, fill_color);
-                    } else {
-                        draw_filled_rect_mut(&mut img, rect, fill_color);
-                    }
+                AnnotationData::Point(p) => backend.draw_point((p.x, p.y), (thickness / 2.0).max(1.0), color)?,
+                AnnotationData::Line(l) => backend.draw_line(l, thickness, color)?,
+                AnnotationData::RectOrOval(r) => backend.draw_rect_or_oval(r, Some(thickness), color)?,
+                AnnotationData::FilledRectOrOval(f) => backend.draw_rect_or_oval(&f.rect, None, f.fill)?,
+                AnnotationData::Text(t) => backend.draw_text(t, color)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Render backend drawing anti-aliased annotations in place onto an `RgbaImage`.
+pub struct ImageBackend<'a> {
+    image: &'a mut RgbaImage,
+    blend: bool,
+}
+
+impl<'a> ImageBackend<'a> {
+    /// Wrap an image; see `render_to_image` for `blend`.
+    pub fn new(image: &'a mut RgbaImage, blend: bool) -> Self {
+        Self { image, blend }
+    }
+}
+
+impl RenderBackend for ImageBackend<'_> {
+    fn canvas_size(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    fn draw_point(&mut self, center: (f64, f64), radius: f64, color: Color) -> Result<(), Error> {
+        draw_segment(self.image, center, center, radius, color, self.blend);
+        Ok(())
+    }
+
+    fn draw_line(&mut self, line: &Line, thickness: f64, color: Color) -> Result<(), Error> {
+        draw_line(self.image, line, thickness, color, self.blend);
+        Ok(())
+    }
+
+    fn draw_rect_or_oval(&mut self, rect: &RectOrOval, thickness: Option<f64>, color: Color) -> Result<(), Error> {
+        if rect.oval {
+            draw_ellipse(self.image, rect, thickness, color, self.blend);
+        } else {
+            draw_rect(self.image, rect, thickness, color, self.blend);
+        }
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &Text, color: Color) -> Result<(), Error> {
+        draw_text(self.image, text, color, self.blend);
+        Ok(())
+    }
+}
+
+/// Split a styled line into the segments to stroke with round caps of radius
+/// `thickness / 2`: the line itself, its dashes or its dots. Only the part of
+/// the line that can touch a canvas of the given size is returned.
+pub(crate) fn line_segments(line: &Line, thickness: f64, canvas_size: (u32, u32)) -> Vec<((f64, f64), (f64, f64))> {
+    let (x_start, y_start, x_end, y_end) = line.as_tuple();
+    let margin = thickness / 2.0 + 1.0;
+    let bounds = (-margin, -margin, canvas_size.0 as f64 + margin, canvas_size.1 as f64 + margin);
+    let Some((t_min, t_max)) = clip_segment((x_start, y_start), (x_end, y_end), bounds) else {
+        return Vec::new();
+    };
+
+    let length = (x_end - x_start).hypot(y_end - y_start);
+    let (dx, dy) = if length > 0.0 {
+        ((x_end - x_start) / length, (y_end - y_start) / length)
+    } else {
+        (0.0, 0.0)
+    };
+    let point = |t: f64| (x_start + dx * t, y_start + dy * t);
+    let Some((dash, period)) = dash_pattern(line.style, thickness) else {
+        return vec![(point(t_min * length), point(t_max * length))];
+    };
+
+    // visible dashes, keeping the dash phase of the whole line; the count is bounded
+    // by the visible length in case the dash indices exceed the float precision
+    let first = ((t_min * length - dash) / period).ceil().max(0.0);
+    let last = (t_max * length / period).floor();
+    let count = (last - first + 1.0).min(((t_max - t_min) * length / period).ceil() + 2.0).max(0.0) as usize;
+    (0..count)
+        .map(|i| {
+            let t = (first + i as f64) * period;
+            (point(t), point((t + dash).min(length)))
+        })
+        .collect()
+}
+
+/// Clip a segment to a rectangle `(left, top, right, bottom)` (Liang-Barsky).
+/// * Returns:
+///     - (`Option<(f64, f64)>`) Range of the visible part along the segment, from 0 at
+///       `start` to 1 at `end`; `None` if the segment misses the rectangle.
+fn clip_segment(start: (f64, f64), end: (f64, f64), bounds: (f64, f64, f64, f64)) -> Option<(f64, f64)> {
+    let (left, top, right, bottom) = bounds;
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let (mut t_min, mut t_max) = (0.0f64, 1.0f64);
+    for (p, q) in [(-dx, start.0 - left), (dx, right - start.0), (-dy, start.1 - top), (dy, bottom - start.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t_min = t_min.max(q / p);
+        } else {
+            t_max = t_max.min(q / p);
+        }
+    }
+    (t_min <= t_max).then_some((t_min, t_max))
+}
+
+/// Return the dash length and period of a line style, `None` if solid;
+/// dashes and dots are spaced relative to the thickness.
+pub(crate) fn dash_pattern(style: LineStyle, thickness: f64) -> Option<(f64, f64)> {
+    match style {
+        LineStyle::Solid => None,
+        // round caps extend every dash by the thickness
+        LineStyle::Dashed => Some((2.0 * thickness.max(2.0), 4.0 * thickness.max(2.0) + thickness)),
+        LineStyle::Dotted => Some((0.0, 2.0 * thickness.max(1.5))),
+    }
+}
+
+/// Paint `color` into every pixel of `bounds` (`(left, top, right, bottom)` in pixels)
+/// weighted by the coverage of the pixel, sampled at its center. The bounds are clipped
+/// to the image. With `blend`, the color is composited over the pixel using its alpha
+/// (source-over); otherwise it replaces the pixel, alpha included.
+fn draw_coverage(
+    img: &mut RgbaImage, bounds: (f64, f64, f64, f64), color: Color, blend: bool, coverage: impl Fn(f64, f64) -> f64,
+) {
+    let (width, height) = img.dimensions();
+    let (left, top, right, bottom) = bounds;
+    let x_range = (left.floor().max(0.0) as u32)..(right.ceil().min(width as f64).max(0.0) as u32);
+    let y_range = (top.floor().max(0.0) as u32)..(bottom.ceil().min(height as f64).max(0.0) as u32);
+    let source = [color.r, color.g, color.b, color.a.unwrap_or(255)].map(|c| c.clamp(0, 255) as f64);
+
+    for y in y_range {
+        for x in x_range.clone() {
+            let weight = coverage(x as f64 + 0.5, y as f64 + 0.5).clamp(0.0, 1.0);
+            if weight <= 0.0 {
+                continue;
+            }
+            let pixel = img.get_pixel_mut(x, y);
+            if blend {
+                pixel.0 = composite(pixel.0, source, weight);
+            } else {
+                for (channel, &value) in pixel.0.iter_mut().zip(&source) {
+                    *channel = (*channel as f64 * (1.0 - weight) + value * weight).round() as u8;
                 }
             }
         }
     }
+}
 
-    DynamicImage::ImageRgba8(img)
+/// Composite a non-premultiplied RGBA color over a pixel (source-over), with the
+/// alpha of the color scaled by `coverage`.
+fn composite(pixel: [u8; 4], color: [f64; 4], coverage: f64) -> [u8; 4] {
+    let source_alpha = color[3] / 255.0 * coverage;
+    let pixel_alpha = pixel[3] as f64 / 255.0;
+    let alpha = source_alpha + pixel_alpha * (1.0 - source_alpha);
+    if alpha <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+
+    let mut result = [0; 4];
+    for i in 0..3 {
+        let value = (color[i] * source_alpha + pixel[i] as f64 * pixel_alpha * (1.0 - source_alpha)) / alpha;
+        result[i] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    result[3] = (alpha * 255.0).round() as u8;
+    result
+}
+
+/// Draw a line in its style.
+fn draw_line(img: &mut RgbaImage, line: &Line, thickness: f64, color: Color, blend: bool) {
+    // keep thin dots visible
+    let radius = match line.style {
+        LineStyle::Dotted => (thickness / 2.0).max(0.75),
+        _ => thickness / 2.0,
+    };
+    for (start, end) in line_segments(line, thickness, img.dimensions()) {
+        draw_segment(img, start, end, radius, color, blend);
+    }
+}
+
+/// Draw an anti-aliased segment with round caps; a point if both ends are equal.
+fn draw_segment(img: &mut RgbaImage, start: (f64, f64), end: (f64, f64), radius: f64, color: Color, blend: bool) {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_sq = dx * dx + dy * dy;
+    let bounds = (
+        start.0.min(end.0) - radius - 1.0,
+        start.1.min(end.1) - radius - 1.0,
+        start.0.max(end.0) + radius + 1.0,
+        start.1.max(end.1) + radius + 1.0,
+    );
+    draw_coverage(img, bounds, color, blend, |x, y| {
+        // distance of the pixel center to the closest point of the segment
+        let t = if length_sq > 0.0 {
+            (((x - start.0) * dx + (y - start.1) * dy) / length_sq).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let distance = (x - start.0 - t * dx).hypot(y - start.1 - t * dy);
+        radius + 0.5 - distance
+    });
+}
+
+/// Draw the outline of a rectangle with the given line thickness, or fill it if `None`.
+fn draw_rect(img: &mut RgbaImage, rect: &RectOrOval, thickness: Option<f64>, color: Color, blend: bool) {
+    // area of the pixel at (x, y) covered by a rectangle
+    let area = |x: f64, y: f64, (left, top, right, bottom): (f64, f64, f64, f64)| {
+        let w = ((x + 0.5).min(right) - (x - 0.5).max(left)).max(0.0);
+        let h = ((y + 0.5).min(bottom) - (y - 0.5).max(top)).max(0.0);
+        w * h
+    };
+
+    let (left, top, right, bottom) = rect.as_tuple();
+    let (left, top, right, bottom) = (left.min(right), top.min(bottom), left.max(right), top.max(bottom));
+    match thickness {
+        Some(thickness) => {
+            let half = thickness / 2.0;
+            let outer = (left - half, top - half, right + half, bottom + half);
+            let inner = (left + half, top + half, right - half, bottom - half);
+            draw_coverage(img, outer, color, blend, |x, y| area(x, y, outer) - area(x, y, inner));
+        }
+        None => {
+            let bounds = (left, top, right, bottom);
+            draw_coverage(img, bounds, color, blend, |x, y| area(x, y, bounds));
+        }
+    }
+}
+
+/// Draw the outline of the ellipse inscribed in a rectangle with the given line
+/// thickness, or fill it if `None`.
+fn draw_ellipse(img: &mut RgbaImage, rect: &RectOrOval, thickness: Option<f64>, color: Color, blend: bool) {
+    let (left, top, right, bottom) = rect.as_tuple();
+    let (cx, cy) = ((left + right) / 2.0, (top + bottom) / 2.0);
+    let (rx, ry) = (((right - left) / 2.0).abs().max(0.5), ((bottom - top) / 2.0).abs().max(0.5));
+
+    // approximate signed distance of a point to the ellipse, negative inside
+    let distance = move |x: f64, y: f64| {
+        let (u, v) = ((x - cx) / rx, (y - cy) / ry);
+        let f = u.hypot(v);
+        let gradient = (u / rx).hypot(v / ry);
+        if gradient > 0.0 {
+            (f - 1.0) * f / gradient
+        } else {
+            -rx.min(ry)
+        }
+    };
+
+    let margin = thickness.unwrap_or(0.0) / 2.0 + 1.0;
+    let bounds = (cx - rx - margin, cy - ry - margin, cx + rx + margin, cy + ry + margin);
+    match thickness {
+        Some(thickness) => {
+            draw_coverage(img, bounds, color, blend, |x, y| thickness / 2.0 + 0.5 - distance(x, y).abs())
+        }
+        None => draw_coverage(img, bounds, color, blend, |x, y| 0.5 - distance(x, y)),
+    }
+}
+
+/// Return the bundled font, parsed on first use.
+fn default_font() -> &'static FontRef<'static> {
+    static FONT: OnceLock<FontRef<'static>> = OnceLock::new();
+    FONT.get_or_init(|| FontRef::try_from_slice(DEFAULT_FONT).expect("the bundled font is valid"))
+}
+
+/// Distance from the top of a line of text to its baseline in pixels.
+pub(crate) fn text_ascent(font_size: f64) -> f64 {
+    default_font().as_scaled(PxScale::from(font_size as f32)).ascent() as f64
+}
+
+/// Width of a single line of text in pixels.
+fn text_width<F: Font>(font: &impl ScaleFont<F>, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Black or white, whichever reads better on the given background.
+fn contrast_color(background: &Color) -> Color {
+    let luminance = 0.299 * background.r as f64 + 0.587 * background.g as f64 + 0.114 * background.b as f64;
+    if luminance > 150.0 {
+        Colors::BLACK
+    } else {
+        Colors::WHITE
+    }
+}
+
+/// Largest area of a glyph relative to the image; larger glyphs are not drawn
+/// as their coverage would not fit into memory.
+const MAX_GLYPH_AREA: f32 = 4.0;
+
+/// Draw a line of text with the bundled font, on its background box if any.
+fn draw_text(img: &mut RgbaImage, text: &Text, color: Color, blend: bool) {
+    let (left, top, right, bottom) = text.bounds();
+    if ![left, top, right, bottom].iter().all(|value| value.is_finite()) {
+        return;
+    }
+    if let Some(background) = text.background {
+        let padding = text.padding();
+        let rect = RectOrOval::new(left - padding, top - padding, right + padding, bottom + padding, false);
+        draw_rect(img, &rect, None, background, blend);
+    }
+
+    // rasterize the glyphs into a coverage mask of the pixels below them
+    let font = default_font();
+    let scale = PxScale::from(text.font_size as f32);
+    let scaled = font.as_scaled(scale);
+    let mut outlines = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scale, point(left as f32 + caret, top as f32 + scaled.ascent()));
+        outlines.extend(font.outline_glyph(glyph));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    // skip glyphs outside of the image and glyphs far larger than it
+    let (width, height) = img.dimensions();
+    let max_area = MAX_GLYPH_AREA * (width as f32 * height as f32).max(1.0);
+    outlines.retain(|outline| {
+        let bounds = outline.px_bounds();
+        bounds.max.x > 0.0
+            && bounds.max.y > 0.0
+            && bounds.min.x < width as f32
+            && bounds.min.y < height as f32
+            && bounds.width() * bounds.height() <= max_area
+    });
+    let Some(first) = outlines.first() else {
+        return;
+    };
+
+    // the mask only covers the visible part of the text
+    let mut bounds = first.px_bounds();
+    for outline in &outlines {
+        let glyph_bounds = outline.px_bounds();
+        bounds.min.x = bounds.min.x.min(glyph_bounds.min.x);
+        bounds.min.y = bounds.min.y.min(glyph_bounds.min.y);
+        bounds.max.x = bounds.max.x.max(glyph_bounds.max.x);
+        bounds.max.y = bounds.max.y.max(glyph_bounds.max.y);
+    }
+    let (mask_left, mask_top) = (bounds.min.x.max(0.0) as i64, bounds.min.y.max(0.0) as i64);
+    let mask_width = (bounds.max.x.min(width as f32) as i64 - mask_left) as usize;
+    let mask_height = (bounds.max.y.min(height as f32) as i64 - mask_top) as usize;
+    let mut mask = vec![0f32; mask_width * mask_height];
+    for outline in &outlines {
+        let glyph_bounds = outline.px_bounds();
+        let (glyph_left, glyph_top) = (glyph_bounds.min.x as i64, glyph_bounds.min.y as i64);
+        outline.draw(|x, y, coverage| {
+            let (column, row) = (glyph_left + x as i64 - mask_left, glyph_top + y as i64 - mask_top);
+            if column < 0 || row < 0 || column as usize >= mask_width || row as usize >= mask_height {
+                return;
+            }
+            let value = &mut mask[row as usize * mask_width + column as usize];
+            *value = (*value + coverage).min(1.0);
+        });
+    }
+
+    let mask_bounds = (
+        mask_left as f64,
+        mask_top as f64,
+        (mask_left + mask_width as i64) as f64,
+        (mask_top + mask_height as i64) as f64,
+    );
+    draw_coverage(img, mask_bounds, color, blend, |x, y| {
+        let (column, row) = ((x - 0.5) as i64 - mask_left, (y - 0.5) as i64 - mask_top);
+        if column < 0 || row < 0 || column as usize >= mask_width || row as usize >= mask_height {
+            return 0.0;
+        }
+        mask[row as usize * mask_width + column as usize] as f64
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(data: AnnotationData, thickness: f64) -> RgbaImage {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 40, Rgba([0, 0, 0, 255])));
+        let annotations = vec![Annotation::new(vec![data], false, thickness, Colors::WHITE)];
+        render_to_image(&annotations, &image, None).to_rgba8()
+    }
+
+    #[test]
+    fn test_render_shapes() {
+        // hollow oval: the outline follows the ellipse, not the bounding box
+        let oval = render(AnnotationData::RectOrOval(RectOrOval::new(10., 10., 30., 30., true)), 2.);
+        assert_eq!(oval.get_pixel(20, 10).0[0], 255);
+        assert_eq!(oval.get_pixel(20, 20).0[0], 0);
+        assert_eq!(oval.get_pixel(11, 11).0[0], 0);
+
+        // filled oval leaves the corners of the bounding box untouched
+        let fill = FilledRectOrOval::new(RectOrOval::new(10., 10., 30., 30., true), Colors::RED);
+        let filled = render(AnnotationData::FilledRectOrOval(fill), 1.);
+        assert_eq!(filled.get_pixel(20, 20).0, [255, 0, 0, 255]);
+        assert_eq!(filled.get_pixel(11, 11).0, [0, 0, 0, 255]);
+
+        // rectangle outline of the given thickness
+        let rect = render(AnnotationData::RectOrOval(RectOrOval::new(10., 10., 30., 30., false)), 4.);
+        assert_eq!(rect.get_pixel(8, 20).0[0], 255);
+        assert_eq!(rect.get_pixel(11, 20).0[0], 255);
+        assert_eq!(rect.get_pixel(13, 20).0[0], 0);
+
+        // thick, anti-aliased line
+        let line = render(AnnotationData::Line(Line::new(5., 20., 35., 20., false)), 5.);
+        assert_eq!(line.get_pixel(20, 18).0[0], 255);
+        assert_eq!(line.get_pixel(20, 21).0[0], 255);
+        assert_eq!(line.get_pixel(20, 25).0[0], 0);
+        let edge = render(AnnotationData::Line(Line::new(5., 20.25, 35., 20.25, false)), 1.).get_pixel(20, 20).0[0];
+        assert!(edge > 0 && edge < 255);
+
+        // points are round
+        let point = render(AnnotationData::Point(Point::new(20., 20.)), 8.);
+        assert_eq!(point.get_pixel(20, 17).0[0], 255);
+        assert_eq!(point.get_pixel(16, 16).0[0], 0);
+    }
+
+    #[test]
+    fn test_render_blending() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 20, Rgba([0, 0, 200, 255])));
+        let red = Color::new(Some(255), None, None, Some(128));
+        let fill = FilledRectOrOval::new(RectOrOval::new(0., 0., 10., 20., false), red);
+        let annotations = vec![Annotation::new(vec![AnnotationData::FilledRectOrOval(fill)], false, 1., Colors::RED)];
+
+        // the underlying pixels shine through a semi-transparent fill
+        let blended = render_to_image(&annotations, &image, Some(true)).to_rgba8();
+        assert_eq!(blended.get_pixel(5, 5).0, [128, 0, 100, 255]);
+        assert_eq!(blended.get_pixel(15, 5).0, [0, 0, 200, 255]);
+
+        // without blending the color replaces the pixels
+        let replaced = render_to_image(&annotations, &image, None).to_rgba8();
+        assert_eq!(replaced.get_pixel(5, 5).0, [255, 0, 0, 128]);
+
+        // over a transparent pixel, the color keeps its own value
+        assert_eq!(composite([0, 0, 0, 0], [255., 0., 0., 128.], 1.), [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn test_render_text() {
+        let text = Text::new(20., 20., "0.98", 16.).with_anchor(TextAnchor::Center);
+        let (left, top, right, bottom) = text.bounds();
+        assert!(((left + right) / 2. - 20.).abs() <= 0.5 && ((top + bottom) / 2. - 20.).abs() <= 0.5);
+        assert!(right - left > 16. && bottom - top >= 16.);
+
+        // glyphs are drawn inside the text box only
+        let image = render(AnnotationData::Text(text.clone()), 1.);
+        let inked = image.enumerate_pixels().filter(|(_, _, p)| p.0[0] > 0).collect::<Vec<_>>();
+        assert!(!inked.is_empty());
+        assert!(inked.iter().all(|(x, y, _)| {
+            (*x as f64) >= left - 1. && (*x as f64) < right + 1. && (*y as f64) >= top && (*y as f64) < bottom
+        }));
+
+        // the background box is filled around the text
+        let labelled = render(AnnotationData::Text(text.with_background(Colors::RED)), 1.);
+        assert_eq!(labelled.get_pixel(left as u32 - 1, top as u32 - 1).0, [255, 0, 0, 255]);
+
+        // score labels of detections
+        let detection = Detection::new(vec![0.25, 0.5, 0.75, 0.75], 0.875);
+        let annotations = detection_labels_to_render_data(&[detection], Colors::GREEN, 12., true, None);
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].color.as_tuple(), Colors::WHITE.as_tuple());
+        match &annotations[0].data[0] {
+            AnnotationData::Text(label) => {
+                assert_eq!(label.text(), "0.88");
+                assert_eq!(label.position(), (0.25, 0.5));
+                assert_eq!(label.anchor(), TextAnchor::BottomLeft);
+            }
+            _ => panic!("expected a score label"),
+        }
+    }
+
+    #[test]
+    fn test_render_clipping() {
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+
+        // points on the edges and corners are clipped, points far outside are ignored
+        for (x, y) in [(0., 0.), (40., 40.), (0., 20.), (39.5, 0.5), (-3., -3.), (1e9, -1e9), (-1e300, 5.)] {
+            let image = render(AnnotationData::Point(Point::new(x, y)), 4.);
+            let inked = image.pixels().filter(|p| **p != black).count();
+            assert!(inked <= 8, "({}, {}): {} pixels", x, y, inked);
+        }
+        assert_eq!(*render(AnnotationData::Point(Point::new(0., 0.)), 4.).get_pixel(0, 0), white);
+
+        // non-finite positions are skipped
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let image = render(AnnotationData::Point(Point::new(value, 20.)), 4.);
+            assert!(image.pixels().all(|p| *p == black));
+            let image = render(AnnotationData::Line(Line::new(0., 0., value, 20., false)), 2.);
+            assert!(image.pixels().all(|p| *p == black));
+        }
+
+        // rectangles partly outside, inverted or far away
+        let fill = |rect| render(AnnotationData::FilledRectOrOval(FilledRectOrOval::new(rect, Colors::WHITE)), 1.);
+        let image = fill(RectOrOval::new(-10., -10., 5., 5., false));
+        assert_eq!(image.pixels().filter(|p| **p == white).count(), 25);
+        let image = fill(RectOrOval::new(45., 5., 35., -5., false));
+        assert_eq!(image.pixels().filter(|p| **p == white).count(), 5 * 5);
+        assert!(fill(RectOrOval::new(50., 50., 60., 60., true)).pixels().all(|p| *p == black));
+        let image = fill(RectOrOval::new(-1e12, -1e12, 1e12, 1e12, true));
+        assert!(image.pixels().all(|p| *p == white));
+        let outline = RectOrOval::new(-1e9, 10., 1e9, 1e9, false);
+        let image = render(AnnotationData::RectOrOval(outline), 2.);
+        assert_eq!(*image.get_pixel(20, 10), white);
+        assert_eq!(*image.get_pixel(20, 20), black);
+
+        // lines crossing the canvas and very long dashed lines
+        let image = render(AnnotationData::Line(Line::new(-100., 20.5, 200., 20.5, false)), 1.);
+        assert!((0..40).all(|x| *image.get_pixel(x, 20) == white));
+        let line = Line::new(-1e12, 20.5, 1e12, 20.5, false).with_style(LineStyle::Dotted);
+        // dots every 4 px from x = 0 to x = 40
+        assert_eq!(line_segments(&line, 2., (40, 40)).len(), 11);
+        let image = render(AnnotationData::Line(line), 2.);
+        assert!((0..40).any(|x| *image.get_pixel(x, 20) == white));
+        assert!(line_segments(&Line::new(-10., -10., -5., 50., false), 2., (40, 40)).is_empty());
+
+        // text larger than the canvas; glyphs far larger than it are skipped, but not their background
+        let image = render(AnnotationData::Text(Text::new(-5., -5., "W", 60.)), 1.);
+        assert!(image.pixels().any(|p| *p != black));
+        for font_size in [1e6, 1e12, 1e300] {
+            let text = Text::new(-5., -5., "W", font_size).with_background(Colors::WHITE);
+            let image = render(AnnotationData::Text(text), 1.);
+            assert!(font_size > 1e38 || image.pixels().all(|p| *p == white), "{}", font_size);
+        }
+
+        // normalized landmarks outside [0, 1], e.g. projected landmarks of a cropped face
+        let landmarks = vec![Landmark::new(-0.2, 0.5, 0.), Landmark::new(0.5, 0.5, 0.), Landmark::new(1.3, 1.1, 0.)];
+        let annotations = landmarks_to_render_data(landmarks, vec![(0, 1), (1, 2)], None, None, Some(2.), None, None);
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 40, black));
+        let rendered = render_to_image(&annotations, &image, Some(true)).to_rgba8();
+        assert_ne!(*rendered.get_pixel(0, 20), black);
+        assert_ne!(*rendered.get_pixel(20, 20), black);
+
+        // text running off the canvas
+        let text = Text::new(30., 38., "clipped label", 20.).with_background(Colors::RED);
+        let image = render(AnnotationData::Text(text), 1.);
+        assert_eq!(*image.get_pixel(39, 39), Rgba([255, 0, 0, 255]));
+        let text = Text::new(-1e9, 1e9, "far away", 20.);
+        assert!(render(AnnotationData::Text(text), 1.).pixels().all(|p| *p == black));
+    }
+
+    #[test]
+    fn test_render_line_styles() {
+        let row = |style: LineStyle| {
+            let line = Line::new(0., 10.5, 40., 10.5, false).with_style(style);
+            let image = render(AnnotationData::Line(line), 2.);
+            (0..40).filter(|&x| image.get_pixel(x, 10).0[0] == 255).count()
+        };
+        let (solid, dashed, dotted) = (row(LineStyle::Solid), row(LineStyle::Dashed), row(LineStyle::Dotted));
+        assert_eq!(solid, 40);
+        assert!(dashed > 10 && dashed < 35, "{}", dashed);
+        assert!(dotted > 3 && dotted < dashed, "{}", dotted);
+        assert_eq!(Line::new(0., 0., 1., 1., true).style(), LineStyle::Dashed);
+    }
 }
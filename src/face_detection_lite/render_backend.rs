@@ -0,0 +1,595 @@
+use crate::face_detection_lite::render::{
+    dash_pattern, line_segments, text_ascent, Color, Line, RectOrOval, RenderBackend, Text,
+};
+use anyhow::Error;
+#[cfg(feature = "opencv")]
+use opencv::core::{add_weighted, Mat, MatTraitConst, Point, Rect, Scalar, Vector};
+#[cfg(feature = "opencv")]
+use std::f64::consts::{PI, TAU};
+#[cfg(feature = "opencv")]
+use opencv::imgproc::{self, FILLED, FONT_HERSHEY_SIMPLEX, LINE_AA};
+
+/// Fractional bits of the fixed-point coordinates passed to OpenCV.
+#[cfg(feature = "opencv")]
+const SHIFT: i32 = 4;
+/// Largest deviation in pixels of the polygons approximating ovals.
+#[cfg(feature = "opencv")]
+const OVAL_TOLERANCE: f64 = 0.125;
+
+/// Render backend drawing annotations in place onto an OpenCV matrix with
+/// `opencv::imgproc`. The matrix is expected to be RGB (or RGBA) like the
+/// matrices of `convert_image_to_mat`; the alpha of colors is blended into it.
+#[cfg(feature = "opencv")]
+pub struct MatBackend<'a> {
+    mat: &'a mut Mat,
+}
+
+#[cfg(feature = "opencv")]
+impl<'a> MatBackend<'a> {
+    pub fn new(mat: &'a mut Mat) -> Self {
+        Self { mat }
+    }
+
+    /// Draw with the opaque color, blending the result into the matrix if the
+    /// color is semi-transparent. `draw` receives the matrix and the origin of
+    /// its coordinates within the canvas; semi-transparent primitives are drawn
+    /// into a copy of just the part of the canvas within `bounds` (in pixels).
+    fn paint(
+        &mut self, color: Color, bounds: (f64, f64, f64, f64),
+        draw: impl Fn(&mut Mat, Scalar, (f64, f64)) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let scalar = Scalar::new(color.r as f64, color.g as f64, color.b as f64, 255.0);
+        let alpha = color.a.unwrap_or(255).clamp(0, 255) as f64 / 255.0;
+        if alpha >= 1.0 {
+            return draw(self.mat, scalar, (0.0, 0.0));
+        }
+
+        let Some(rect) = clipped_rect(bounds, self.canvas_size()) else {
+            return Ok(());
+        };
+        let mut target = Mat::roi_mut(self.mat, rect)?;
+        let mut overlay = target.try_clone()?;
+        draw(&mut overlay, scalar, (rect.x as f64, rect.y as f64))?;
+        let mut blended = Mat::default();
+        add_weighted(&overlay, alpha, &target, 1.0 - alpha, 0.0, &mut blended, -1)?;
+        blended.copy_to(&mut target)?;
+        Ok(())
+    }
+}
+
+/// Pixels of the canvas covered by a box `(left, top, right, bottom)` in pixels,
+/// or `None` if they do not overlap.
+#[cfg(feature = "opencv")]
+fn clipped_rect(bounds: (f64, f64, f64, f64), canvas_size: (u32, u32)) -> Option<Rect> {
+    let (left, top, right, bottom) = bounds;
+    let left = left.floor().max(0.0) as i32;
+    let top = top.floor().max(0.0) as i32;
+    let right = right.ceil().min(canvas_size.0 as f64) as i32;
+    let bottom = bottom.ceil().min(canvas_size.1 as f64) as i32;
+    (right > left && bottom > top).then(|| Rect::new(left, top, right - left, bottom - top))
+}
+
+/// Check whether a box `(left, top, right, bottom)` in pixels overlaps a canvas.
+#[cfg(feature = "opencv")]
+fn overlaps_canvas(bounds: (f64, f64, f64, f64), canvas_size: (u32, u32)) -> bool {
+    let (left, top, right, bottom) = bounds;
+    right >= 0.0 && bottom >= 0.0 && left <= canvas_size.0 as f64 && top <= canvas_size.1 as f64
+}
+
+/// Convert a position in pixels to fixed-point OpenCV coordinates relative to
+/// `origin`, where pixel centers are at integer positions.
+#[cfg(feature = "opencv")]
+fn fixed_point(point: (f64, f64), origin: (f64, f64)) -> Point {
+    let scale = (1 << SHIFT) as f64;
+    let (x, y) = (point.0 - origin.0 - 0.5, point.1 - origin.1 - 0.5);
+    Point::new((x * scale).round() as i32, (y * scale).round() as i32)
+}
+
+/// Angles in `[0, 2π)` at which `cos` is within `[low, high]`.
+#[cfg(feature = "opencv")]
+fn cos_intervals(low: f64, high: f64) -> Vec<(f64, f64)> {
+    if high < -1.0 || low > 1.0 {
+        return Vec::new();
+    }
+    let (start, end) = (high.min(1.0).acos(), low.max(-1.0).acos());
+    vec![(start, end), (TAU - end, TAU - start)]
+}
+
+/// Intersect two sets of angle intervals within `[0, 2π)`, sorted by start.
+#[cfg(feature = "opencv")]
+fn intersect_intervals(a: &[(f64, f64)], b: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut intervals: Vec<(f64, f64)> = a
+        .iter()
+        .flat_map(|&(a0, a1)| b.iter().map(move |&(b0, b1)| (a0.max(b0), a1.min(b1))))
+        .filter(|(start, end)| start <= end)
+        .collect();
+    intervals.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // join touching intervals, including across the zero angle
+    let mut merged: Vec<(f64, f64)> = Vec::new();
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.0 <= last.1 => last.1 = last.1.max(interval.1),
+            _ => merged.push(interval),
+        }
+    }
+    if merged.len() > 1 && merged[0].0 <= 0.0 && merged[merged.len() - 1].1 >= TAU {
+        let first = merged.remove(0);
+        merged.last_mut().unwrap().1 = first.1 + TAU;
+    }
+    merged
+}
+
+/// Position of a point on the boundary of a box `(left, top, right, bottom)`,
+/// increasing clockwise from 0 at the top left to 4 back at the top left.
+#[cfg(feature = "opencv")]
+fn boundary_position(point: (f64, f64), bounds: (f64, f64, f64, f64)) -> f64 {
+    let (left, top, right, bottom) = bounds;
+    let (x, y) = ((point.0 - left) / (right - left), (point.1 - top) / (bottom - top));
+    let edges = [(y, x), (1.0 - x, 1.0 + y), (1.0 - y, 3.0 - x), (x, 4.0 - y)];
+    let (_, position) = edges.into_iter().min_by(|a, b| a.0.total_cmp(&b.0)).unwrap();
+    position.clamp(0.0, 4.0)
+}
+
+/// Approximate the part of an axis-aligned oval within a box `(left, top, right,
+/// bottom)` in pixels by polygons. Ovals much larger than the box keep their
+/// exact shape within it, as only the visible arcs are sampled.
+/// * Args:
+///     - center (`(f64, f64)`): Center of the oval in pixels.
+///     - axes (`(f64, f64)`): Horizontal and vertical half axes in pixels.
+///     - bounds (`(f64, f64, f64, f64)`): Box to clip the oval to.
+///     - filled (`bool`): Return the filled area instead of the outline.
+///
+/// * Returns:
+///     - (`Vec<Vec<(f64, f64)>>`) Open polylines of the outline, or a single
+///       polygon of the filled area.
+#[cfg(feature = "opencv")]
+fn clipped_oval(
+    center: (f64, f64), axes: (f64, f64), bounds: (f64, f64, f64, f64), filled: bool,
+) -> Vec<Vec<(f64, f64)>> {
+    let (left, top, right, bottom) = bounds;
+    let (a, b) = (axes.0.max(f64::EPSILON), axes.1.max(f64::EPSILON));
+    let point = |angle: f64| (center.0 + a * angle.cos(), center.1 + b * angle.sin());
+
+    // angles of the outline within the box; sin(t) = cos(t - π/2)
+    let horizontal = cos_intervals((left - center.0) / a, (right - center.0) / a);
+    let vertical: Vec<(f64, f64)> = cos_intervals((top - center.1) / b, (bottom - center.1) / b)
+        .into_iter()
+        .flat_map(|(start, end)| {
+            let (start, end) = (start + PI / 2.0, end + PI / 2.0);
+            if end <= TAU {
+                vec![(start, end)]
+            } else if start >= TAU {
+                vec![(start - TAU, end - TAU)]
+            } else {
+                vec![(start, TAU), (0.0, end - TAU)]
+            }
+        })
+        .collect();
+    let intervals = intersect_intervals(&horizontal, &vertical);
+
+    // sample the arcs finely enough for the tolerance
+    let step = (2.0 * (1.0 - OVAL_TOLERANCE / a.max(b)).clamp(-1.0, 1.0).acos()).clamp(1e-6, PI / 16.0);
+    let arcs: Vec<Vec<(f64, f64)>> = intervals
+        .iter()
+        .map(|&(start, end)| {
+            let count = ((end - start) / step).ceil().clamp(1.0, 1e5) as usize;
+            (0..=count)
+                .map(|n| point(start + (end - start) * n as f64 / count as f64))
+                .collect()
+        })
+        .collect();
+    if !filled {
+        return arcs;
+    }
+
+    if arcs.is_empty() {
+        // the box is either entirely inside or outside the oval
+        let (x, y) = ((left + right) / 2.0, (top + bottom) / 2.0);
+        let inside = ((x - center.0) / a).powi(2) + ((y - center.1) / b).powi(2) <= 1.0;
+        return if inside {
+            vec![vec![(left, top), (right, top), (right, bottom), (left, bottom)]]
+        } else {
+            Vec::new()
+        };
+    }
+
+    // join the arcs along the boundary of the box, in the same clockwise direction
+    let corners = [(right, top), (right, bottom), (left, bottom), (left, top)];
+    let mut polygon = Vec::new();
+    for (n, arc) in arcs.iter().enumerate() {
+        polygon.extend_from_slice(arc);
+        let next = &arcs[(n + 1) % arcs.len()];
+        let exit = boundary_position(arc[arc.len() - 1], bounds);
+        let mut entry = boundary_position(next[0], bounds);
+        if entry < exit {
+            entry += 4.0;
+        }
+        for corner in (exit.floor() as usize + 1)..=(entry.ceil() as usize).saturating_sub(1) {
+            polygon.push(corners[(corner - 1) % 4]);
+        }
+    }
+    vec![polygon]
+}
+
+#[cfg(feature = "opencv")]
+impl RenderBackend for MatBackend<'_> {
+    fn canvas_size(&self) -> (u32, u32) {
+        let (width, height) = (self.mat.cols(), self.mat.rows());
+        (width.max(0) as u32, height.max(0) as u32)
+    }
+
+    fn draw_point(&mut self, center: (f64, f64), radius: f64, color: Color) -> Result<(), Error> {
+        let bounds = (center.0 - radius, center.1 - radius, center.0 + radius, center.1 + radius);
+        if !overlaps_canvas(bounds, self.canvas_size()) {
+            return Ok(());
+        }
+        let bounds = (bounds.0 - 1.0, bounds.1 - 1.0, bounds.2 + 1.0, bounds.3 + 1.0);
+        let radius = (radius * (1 << SHIFT) as f64).round() as i32;
+        self.paint(color, bounds, |mat, scalar, origin| {
+            let center = fixed_point(center, origin);
+            Ok(imgproc::circle(mat, center, radius, scalar, FILLED, LINE_AA, SHIFT)?)
+        })
+    }
+
+    fn draw_line(&mut self, line: &Line, thickness: f64, color: Color) -> Result<(), Error> {
+        let thickness = thickness.round().max(1.0) as i32;
+        let segments = line_segments(line, thickness as f64, self.canvas_size());
+        let margin = thickness as f64 / 2.0 + 1.0;
+        let bounds = segments.iter().fold(
+            (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            |(left, top, right, bottom), &((x0, y0), (x1, y1))| {
+                (left.min(x0).min(x1), top.min(y0).min(y1), right.max(x0).max(x1), bottom.max(y0).max(y1))
+            },
+        );
+        let bounds = (bounds.0 - margin, bounds.1 - margin, bounds.2 + margin, bounds.3 + margin);
+        self.paint(color, bounds, |mat, scalar, origin| {
+            for &(start, end) in &segments {
+                let (start, end) = (fixed_point(start, origin), fixed_point(end, origin));
+                imgproc::line(mat, start, end, scalar, thickness, LINE_AA, SHIFT)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn draw_rect_or_oval(&mut self, rect: &RectOrOval, thickness: Option<f64>, color: Color) -> Result<(), Error> {
+        let (left, top, right, bottom) = rect.as_tuple();
+        let (left, top, right, bottom) = (left.min(right), top.min(bottom), left.max(right), top.max(bottom));
+        let margin = thickness.unwrap_or(0.0) / 2.0 + 1.0;
+        let canvas_size = self.canvas_size();
+        let bounds = (left - margin, top - margin, right + margin, bottom + margin);
+        if !overlaps_canvas(bounds, canvas_size) {
+            return Ok(());
+        }
+        let oval = rect.is_oval();
+        let thickness = thickness.map_or(FILLED, |thickness| thickness.round().max(1.0) as i32);
+        self.paint(color, bounds, |mat, scalar, origin| {
+            if oval {
+                // only the part of the oval near the canvas is drawn, which keeps
+                // ovals far larger than the canvas within the fixed-point range
+                let center = ((left + right) / 2.0, (top + bottom) / 2.0);
+                let axes = ((right - left) / 2.0, (bottom - top) / 2.0);
+                let clip = (-margin, -margin, canvas_size.0 as f64 + margin, canvas_size.1 as f64 + margin);
+                let polygons: Vector<Vector<Point>> = clipped_oval(center, axes, clip, thickness == FILLED)
+                    .into_iter()
+                    .map(|polygon| polygon.into_iter().map(|point| fixed_point(point, origin)).collect())
+                    .collect();
+                if thickness == FILLED {
+                    imgproc::fill_poly(mat, &polygons, scalar, LINE_AA, SHIFT, Point::default())?;
+                } else {
+                    imgproc::polylines(mat, &polygons, false, scalar, thickness, LINE_AA, SHIFT)?;
+                }
+            } else {
+                // pixel edges rather than centers span the rectangle; edges far outside
+                // the canvas are moved closer to stay within the fixed-point range
+                let (width, height) = (canvas_size.0 as f64 + margin, canvas_size.1 as f64 + margin);
+                let start = fixed_point(((left + 0.5).max(-margin), (top + 0.5).max(-margin)), origin);
+                let end = fixed_point(((right - 0.5).min(width), (bottom - 0.5).min(height)), origin);
+                imgproc::rectangle_points(mat, start, end, scalar, thickness, LINE_AA, SHIFT)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn draw_text(&mut self, text: &Text, color: Color) -> Result<(), Error> {
+        // scale the Hershey font to the text box of the bundled font
+        let (left, top, right, bottom) = text.bounds();
+        let mut baseline = 0;
+        let size = imgproc::get_text_size(text.text(), FONT_HERSHEY_SIMPLEX, 1.0, 1, &mut baseline)?;
+        let font_scale = (bottom - top) / (size.height + baseline).max(1) as f64;
+        let thickness = (font_scale * 1.5).round().max(1.0) as i32;
+        let baseline_y = top + size.height as f64 * font_scale;
+
+        if let Some(background) = text.background() {
+            let padding = text.padding();
+            let rect = RectOrOval::new(left - padding, top - padding, right + padding, bottom + padding, false);
+            self.draw_rect_or_oval(&rect, None, background)?;
+        }
+        let content = text.text();
+        let margin = thickness as f64 + 1.0;
+        let text_right = left + size.width as f64 * font_scale;
+        let bounds = (left - margin, top - margin, right.max(text_right) + margin, bottom + margin);
+        self.paint(color, bounds, |mat, scalar, origin| {
+            let position = Point::new((left - origin.0) as i32, (baseline_y - origin.1).round() as i32);
+            let font = FONT_HERSHEY_SIMPLEX;
+            Ok(imgproc::put_text(mat, content, position, font, font_scale, scalar, thickness, LINE_AA, false)?)
+        })
+    }
+}
+
+/// Render backend exporting annotations as an SVG document, e.g. for reports
+/// or as an overlay of the image in a web page.
+#[derive(Debug, Clone)]
+pub struct SvgBackend {
+    width: u32,
+    height: u32,
+    elements: Vec<String>,
+}
+
+impl SvgBackend {
+    /// Create an empty document of the size of the annotated image in pixels.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            elements: Vec::new(),
+        }
+    }
+
+    /// Return the SVG document of everything drawn so far.
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+            self.width, self.height
+        );
+        for element in &self.elements {
+            svg.push_str("  ");
+            svg.push_str(element);
+            svg.push('\n');
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Format a coordinate with at most two decimals.
+fn number(value: f64) -> String {
+    let formatted = format!("{:.2}", value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    if formatted == "-0" {
+        "0".to_string()
+    } else {
+        formatted.to_string()
+    }
+}
+
+/// Return the attributes painting an SVG element with a color, e.g. `fill="rgb(255,0,0)"`.
+fn paint(attribute: &str, color: Color) -> String {
+    let [r, g, b] = [color.r, color.g, color.b].map(|c| c.clamp(0, 255));
+    let mut paint = format!("{}=\"rgb({},{},{})\"", attribute, r, g, b);
+    if let Some(alpha) = color.a.filter(|&alpha| alpha < 255) {
+        paint.push_str(&format!(" {}-opacity=\"{}\"", attribute, number(alpha.max(0) as f64 / 255.0)));
+    }
+    paint
+}
+
+/// Escape text for use in XML content.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+impl RenderBackend for SvgBackend {
+    fn canvas_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn draw_point(&mut self, center: (f64, f64), radius: f64, color: Color) -> Result<(), Error> {
+        self.elements.push(format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" {}/>",
+            number(center.0),
+            number(center.1),
+            number(radius),
+            paint("fill", color)
+        ));
+        Ok(())
+    }
+
+    fn draw_line(&mut self, line: &Line, thickness: f64, color: Color) -> Result<(), Error> {
+        let (x_start, y_start, x_end, y_end) = line.as_tuple();
+        let dashes = match dash_pattern(line.style(), thickness) {
+            Some((dash, period)) => format!(" stroke-dasharray=\"{} {}\"", number(dash), number(period - dash)),
+            None => String::new(),
+        };
+        self.elements.push(format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {} stroke-width=\"{}\" stroke-linecap=\"round\"{}/>",
+            number(x_start),
+            number(y_start),
+            number(x_end),
+            number(y_end),
+            paint("stroke", color),
+            number(thickness),
+            dashes
+        ));
+        Ok(())
+    }
+
+    fn draw_rect_or_oval(&mut self, rect: &RectOrOval, thickness: Option<f64>, color: Color) -> Result<(), Error> {
+        let style = match thickness {
+            Some(thickness) => {
+                format!("fill=\"none\" {} stroke-width=\"{}\"", paint("stroke", color), number(thickness))
+            }
+            None => paint("fill", color),
+        };
+        let (left, top, right, bottom) = rect.as_tuple();
+        let (width, height) = ((right - left).abs(), (bottom - top).abs());
+        let element = if rect.is_oval() {
+            format!(
+                "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" {}/>",
+                number((left + right) / 2.0),
+                number((top + bottom) / 2.0),
+                number(width / 2.0),
+                number(height / 2.0),
+                style
+            )
+        } else {
+            format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+                number(left.min(right)),
+                number(top.min(bottom)),
+                number(width),
+                number(height),
+                style
+            )
+        };
+        self.elements.push(element);
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &Text, color: Color) -> Result<(), Error> {
+        let (left, top, right, bottom) = text.bounds();
+        if let Some(background) = text.background() {
+            let padding = text.padding();
+            let rect = RectOrOval::new(left - padding, top - padding, right + padding, bottom + padding, false);
+            self.draw_rect_or_oval(&rect, None, background)?;
+        }
+        self.elements.push(format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"DejaVu Sans Mono, monospace\" font-size=\"{}\" {}>{}</text>",
+            number(left),
+            number(top + text_ascent(text.font_size())),
+            number(text.font_size()),
+            paint("fill", color),
+            escape(text.text())
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::face_detection_lite::render::{
+        render, Annotation, AnnotationData, Colors, FilledRectOrOval, LineStyle, Point, TextAnchor,
+    };
+
+    fn annotations() -> Vec<Annotation> {
+        let half_red = Color::new(Some(255), None, None, Some(128));
+        vec![
+            Annotation::new(
+                vec![
+                    AnnotationData::RectOrOval(RectOrOval::new(0.25, 0.25, 0.75, 0.5, false)),
+                    AnnotationData::Line(Line::new(0., 0., 1., 1., true)),
+                    AnnotationData::Point(Point::new(0.5, 0.5)),
+                ],
+                true,
+                2.,
+                Colors::GREEN,
+            ),
+            Annotation::new(
+                vec![
+                    AnnotationData::FilledRectOrOval(FilledRectOrOval::new(
+                        RectOrOval::new(10., 10., 30., 20., true),
+                        half_red,
+                    )),
+                    AnnotationData::Text(Text::new(5., 35., "a<b", 12.).with_anchor(TextAnchor::BottomLeft)),
+                ],
+                false,
+                1.,
+                Colors::WHITE,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_svg_backend() {
+        let mut svg = SvgBackend::new(80, 40);
+        render(&annotations(), &mut svg).unwrap();
+        let document = svg.to_svg();
+
+        assert!(document.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"80\" height=\"40\""));
+        assert!(document.contains("<rect x=\"20\" y=\"10\" width=\"40\" height=\"10\" fill=\"none\""));
+        assert!(document.contains("stroke=\"rgb(0,255,0)\" stroke-width=\"2\"/>"));
+        assert!(document.contains("<line x1=\"0\" y1=\"0\" x2=\"80\" y2=\"40\" stroke=\"rgb(0,255,0)\""));
+        assert!(document.contains("stroke-dasharray=\"4 6\""));
+        assert!(document.contains("<circle cx=\"40\" cy=\"20\" r=\"1\" fill=\"rgb(0,255,0)\"/>"));
+        assert!(document.contains(
+            "<ellipse cx=\"20\" cy=\"15\" rx=\"10\" ry=\"5\" fill=\"rgb(255,0,0)\" fill-opacity=\"0.5\"/>"
+        ));
+        assert!(document.contains(">a&lt;b</text>"));
+        assert!(document.ends_with("</svg>\n"));
+
+        assert_eq!(number(2. / 3.), "0.67");
+        assert_eq!(number(-0.001), "0");
+        assert_eq!(dash_pattern(LineStyle::Solid, 2.), None);
+    }
+
+    #[cfg(feature = "opencv")]
+    #[test]
+    fn test_mat_backend() {
+        use opencv::core::{Vec3b, CV_8UC3};
+
+        let mut mat = Mat::new_rows_cols_with_default(40, 80, CV_8UC3, Scalar::all(0.)).unwrap();
+        render(&annotations(), &mut MatBackend::new(&mut mat)).unwrap();
+
+        // semi-transparent fill, rectangle outline and untouched background
+        let fill = mat.at_2d::<Vec3b>(15, 20).unwrap();
+        assert!((fill[0] as i32 - 128).abs() <= 1 && fill[1] == 0 && fill[2] == 0);
+        assert_eq!(mat.at_2d::<Vec3b>(10, 40).unwrap()[1], 255);
+        assert_eq!(*mat.at_2d::<Vec3b>(5, 75).unwrap(), Vec3b::from([0, 0, 0]));
+        // pixels next to the semi-transparent oval keep their color
+        assert_eq!(*mat.at_2d::<Vec3b>(5, 20).unwrap(), Vec3b::from([0, 0, 0]));
+
+        // semi-transparent primitives far from the origin are blended at their position
+        let half_blue = Color::new(None, None, Some(255), Some(128));
+        let rect = FilledRectOrOval::new(RectOrOval::new(62., 2., 78., 8., false), half_blue);
+        let annotation = Annotation::new(vec![AnnotationData::FilledRectOrOval(rect)], false, 1., Colors::WHITE);
+        render(&[annotation], &mut MatBackend::new(&mut mat)).unwrap();
+        let fill = mat.at_2d::<Vec3b>(5, 70).unwrap();
+        assert!(fill[0] == 0 && fill[1] == 0 && (fill[2] as i32 - 128).abs() <= 1);
+        assert_eq!(*mat.at_2d::<Vec3b>(5, 60).unwrap(), Vec3b::from([0, 0, 0]));
+    }
+
+    #[cfg(feature = "opencv")]
+    #[test]
+    fn test_mat_backend_clipping() {
+        use opencv::core::{Vec3b, CV_8UC3};
+
+        let mut mat = Mat::new_rows_cols_with_default(40, 80, CV_8UC3, Scalar::all(0.)).unwrap();
+        let annotations = vec![
+            Annotation::new(
+                vec![
+                    // off-canvas and huge shapes
+                    AnnotationData::RectOrOval(RectOrOval::new(100., 50., 120., 60., true)),
+                    AnnotationData::RectOrOval(RectOrOval::new(-1e12, -1e12, 1e12, 1e12, true)),
+                    AnnotationData::Line(Line::new(-1e12, 20.5, 1e12, 20.5, false)),
+                ],
+                false,
+                2.,
+                Colors::GREEN,
+            ),
+            Annotation::new(
+                vec![
+                    // oval crossing the left edge
+                    AnnotationData::FilledRectOrOval(FilledRectOrOval::new(
+                        RectOrOval::new(-20., 0., 20., 40., true),
+                        Colors::RED,
+                    )),
+                    // oval crossing the right edge with its far side more than 16 canvas sizes away
+                    AnnotationData::FilledRectOrOval(FilledRectOrOval::new(
+                        RectOrOval::new(30., 22., 3000., 38., true),
+                        Colors::BLUE,
+                    )),
+                ],
+                false,
+                1.,
+                Colors::WHITE,
+            ),
+        ];
+        render(&annotations, &mut MatBackend::new(&mut mat)).unwrap();
+
+        assert_eq!(*mat.at_2d::<Vec3b>(30, 5).unwrap(), Vec3b::from([255, 0, 0]));
+        assert_eq!(*mat.at_2d::<Vec3b>(30, 33).unwrap(), Vec3b::from([0, 0, 255]));
+        assert_eq!(*mat.at_2d::<Vec3b>(30, 27).unwrap(), Vec3b::from([0, 0, 0]));
+        assert_eq!(*mat.at_2d::<Vec3b>(20, 60).unwrap(), Vec3b::from([0, 255, 0]));
+        assert_eq!(*mat.at_2d::<Vec3b>(5, 60).unwrap(), Vec3b::from([0, 0, 0]));
+        assert_eq!(*mat.at_2d::<Vec3b>(35, 79).unwrap(), Vec3b::from([0, 0, 0]));
+    }
+}
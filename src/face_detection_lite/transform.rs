@@ -1,16 +1,18 @@
-use std::default;
 use crate::face_detection_lite::types::{BBox, Detection, ImageTensor, Landmark, Rect};
 use anyhow::Error;
-use image::GenericImageView;
+use image::RgbImage;
+use ndarray::parallel::prelude::*;
 use ndarray::Array3;
-use ndarray::{s, Array2, Array4, Axis};
-use opencv::core::{copy_make_border, flip, Point2f, Scalar, Size, Vec3b, Vector, BORDER_CONSTANT};
+use ndarray::{s, Array2, Array4, ArrayViewMut3, Axis};
+#[cfg(feature = "opencv")]
+use opencv::core::{copy_make_border, flip, Point2f, Scalar, Size, Vector, BORDER_CONSTANT, CV_8UC3};
+#[cfg(feature = "opencv")]
 use opencv::imgproc::{resize, INTER_LINEAR};
-use opencv::{imgproc, prelude::*};
+#[cfg(feature = "opencv")]
+use opencv::{core::Mat, imgproc, prelude::*};
 use std::f64::consts::PI;
 use std::f64::EPSILON;
 use std::ops::Add;
-use opencv::imgcodecs::imwrite;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SizeMode {
@@ -183,9 +185,39 @@ pub fn bbox_from_landmarks(landmarks: &[Landmark]) -> Result<BBox, Error> {
 ///             to `True`. Default: `False`
 ///
 /// * Returns:
-///         (`ImageTensor`): Tensor data, padding for reversing letterboxing and
-///         original image dimensions.
+///         (`ImageTensor`): Tensor data of shape `(height, width, 3)`, padding for
+///         reversing letterboxing and original image dimensions.
+#[cfg(feature = "opencv")]
 pub fn image_to_tensor(image: &Mat, roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool, output_range: (f64, f64), flip_horizontal: bool) -> Result<ImageTensor, Error> {
+    let image_size = image.size()?;
+    allocate_tensor((image_size.width, image_size.height), roi, output_size, |output| {
+        image_to_tensor_into(image, output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+    })
+}
+
+/// Same as `image_to_tensor`, but writes the tensor data straight into `output`,
+/// e.g. the input tensor of a model interpreter, instead of allocating it.
+/// * Args:
+///     - output (`&mut [f32]`): Buffer of `width * height * 3` values receiving
+///             the tensor data in `(height, width, 3)` order.
+///
+/// * Returns:
+///         (`(f64, f64, f64, f64)`): Padding for reversing letterboxing.
+#[cfg(feature = "opencv")]
+pub fn image_to_tensor_into(
+    image: &Mat, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool,
+    output_range: (f64, f64), flip_horizontal: bool,
+) -> Result<(f64, f64, f64, f64), Error> {
+    let (roi_image, padding) = warp_roi_image(image, roi, output_size, keep_aspect_ratio, flip_horizontal)?;
+    mat_to_tensor_into(&roi_image, output, output_range)?;
+    Ok(padding)
+}
+
+/// Crop, rotate, letterbox and flip the ROI of an image into an image of the output size.
+#[cfg(feature = "opencv")]
+fn warp_roi_image(
+    image: &Mat, roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool, flip_horizontal: bool,
+) -> Result<(Mat, (f64, f64, f64, f64)), Error> {
     let original_img_shape = image.size()?;
     let mut roi = roi.unwrap_or_else(|| Rect {
         x_center: 0.5,
@@ -237,7 +269,7 @@ pub fn image_to_tensor(image: &Mat, roi: Option<Rect>, output_size: Option<(i32,
     let mut pad_y: f64 = 0.;
 
     if keep_aspect_ratio {
-        let out_aspect = (output_size.1 / output_size.0) as f64;
+        let out_aspect = output_size.1 as f64 / output_size.0 as f64;
         let roi_aspect = roi.height / roi.width;
         let (mut new_width, mut new_height) = (roi.width as i32, roi.height as i32);
 
@@ -274,8 +306,7 @@ pub fn image_to_tensor(image: &Mat, roi: Option<Rect>, output_size: Option<(i32,
         }
 
         let mut resized_image = Mat::default();
-        resize(&roi_image, &mut resized_image, Size::new(output_size.0, output_size.1), 0.0, 0.0, INTER_LINEAR)
-            .unwrap();
+        resize(&roi_image, &mut resized_image, Size::new(output_size.0, output_size.1), 0.0, 0.0, INTER_LINEAR)?;
         roi_image = resized_image;
     }
 
@@ -285,26 +316,63 @@ pub fn image_to_tensor(image: &Mat, roi: Option<Rect>, output_size: Option<(i32,
         roi_image = flipped_image;
     };
 
-    let min_val = output_range.0;
-    let max_val = output_range.1;
-    let img_shape = roi_image.size()?;
+    Ok((roi_image, (pad_x, pad_y, pad_x, pad_y)))
+}
+
+/// Convert the pixels of an 8-bit, 3-channel image into `output` in a single
+/// pass over the contiguous image data.
+#[cfg(feature = "opencv")]
+fn mat_to_tensor_into(image: &Mat, output: &mut [f32], output_range: (f64, f64)) -> Result<(), Error> {
+    if image.typ() != CV_8UC3 {
+        return Err(Error::msg("image must be an 8-bit, 3-channel matrix"));
+    }
+    let continuous;
+    let image = if image.is_continuous() {
+        image
+    } else {
+        continuous = image.try_clone()?;
+        &continuous
+    };
+    let bytes = image.data_bytes()?;
+    if bytes.len() != output.len() {
+        return Err(Error::msg(format!(
+            "output tensor has {} values, expected {}",
+            output.len(),
+            bytes.len()
+        )));
+    }
 
-    let mut tensors = Array3::<f32>::zeros((img_shape.width as usize, img_shape.height as usize, 3usize));
+    let (min_val, max_val) = output_range;
+    let scale = ((max_val - min_val) / 255.0) as f32;
+    let offset = min_val as f32;
+    for (value, &byte) in output.iter_mut().zip(bytes) {
+        *value = byte as f32 * scale + offset;
+    }
+    Ok(())
+}
 
-    for i in 0..3 {
-        for y in 0..img_shape.width as usize {
-            for x in 0..img_shape.height as usize {
-                let pixel_value = roi_image.at_2d::<Vec3b>(y as i32, x as i32).unwrap()[i];
-                tensors[[y, x, i]] = (pixel_value as f64 * (max_val - min_val) / 255.0 + min_val) as f32;
-            }
-        }
+/// Allocate a tensor of the output size (see `image_to_tensor`) and fill it with `write`.
+pub(crate) fn allocate_tensor(
+    image_size: (i32, i32), roi: Option<Rect>, output_size: Option<(i32, i32)>,
+    write: impl FnOnce(&mut [f32]) -> Result<(f64, f64, f64, f64), Error>,
+) -> Result<ImageTensor, Error> {
+    let (width, height) = output_size.unwrap_or_else(|| {
+        let roi = roi
+            .unwrap_or(Rect::new(0.5, 0.5, 1.0, 1.0, 0.0, true))
+            .scaled((image_size.0 as f64, image_size.1 as f64), false);
+        (roi.width as i32, roi.height as i32)
+    });
+    if width <= 0 || height <= 0 {
+        return Err(Error::msg("roi and output_size must not be empty"));
     }
-    let tensor_data = tensors.into_dyn();
+
+    let mut tensors = Array3::<f32>::zeros((height as usize, width as usize, 3));
+    let padding = write(tensors.as_slice_mut().expect("standard layout"))?;
 
     Ok(ImageTensor {
-        tensor_data,
-        padding: (pad_x, pad_y, pad_x, pad_y),
-        original_size: (original_img_shape.width, original_img_shape.height),
+        tensor_data: tensors.into_dyn(),
+        padding,
+        original_size: image_size,
     })
 }
 
@@ -314,7 +382,172 @@ fn vec_to_array2(vec: Vec<(f32, f32)>) -> Array2<f32> {
     Array2::from_shape_vec((rows, 2), flattened).unwrap()
 }
 
-fn perspective_transform_coeff(src_points: &Vec<(f32, f32)>, dst_points: &Vec<(f32, f32)>) -> Result<[f32; 8], Error> {
+/// Pure-Rust version of `image_to_tensor` for RGB images.
+/// The ROI, letterboxing and flipping are applied in a single bilinear
+/// resampling pass; pixels outside of the image are black.
+/// * Args:
+///     - image (`&RgbImage`): Input image.
+///     - roi (`Option<Rect>`): Location within the image where to convert; can be `None`,
+///       in which case the entire image is converted. Rotation is supported.
+///     - output_size (`Option<(i32, i32)>`): Tuple of `(width, height)` describing the
+///       output tensor size; defaults to ROI if `None`.
+///     - keep_aspect_ratio (bool): `False` will scale the image to the output size;
+///       `True` will keep the ROI aspect ratio and apply letterboxing.
+///     - output_range (`(f64, f64)`): Tuple of `(min_val, max_val)` containing the
+///       minimum and maximum value of the output tensor.
+///     - flip_horizontal (`bool`): Flip the resulting image horizontally if set to `True`.
+///
+/// * Returns:
+///     - (`ImageTensor`): Tensor data of shape `(height, width, 3)`, padding for
+///       reversing letterboxing and original image dimensions.
+pub fn rgb_image_to_tensor(
+    image: &RgbImage, roi: Option<Rect>, output_size: Option<(i32, i32)>, keep_aspect_ratio: bool,
+    output_range: (f64, f64), flip_horizontal: bool,
+) -> Result<ImageTensor, Error> {
+    let image_size = (image.width() as i32, image.height() as i32);
+    allocate_tensor(image_size, roi, output_size, |output| {
+        rgb_image_to_tensor_into(image, output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+    })
+}
+
+/// Same as `rgb_image_to_tensor`, but writes the tensor data straight into `output`,
+/// e.g. the input tensor of a model interpreter, instead of allocating it.
+/// * Args:
+///     - output (`&mut [f32]`): Buffer of `width * height * 3` values receiving the
+///       tensor data in `(height, width, 3)` order.
+///
+/// * Returns:
+///     - (`(f64, f64, f64, f64)`): Padding for reversing letterboxing.
+pub fn rgb_image_to_tensor_into(
+    image: &RgbImage, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>,
+    keep_aspect_ratio: bool, output_range: (f64, f64), flip_horizontal: bool,
+) -> Result<(f64, f64, f64, f64), Error> {
+    let (width, height) = image.dimensions();
+    let pixels = PixelBuffer {
+        data: image.as_raw(),
+        width,
+        height,
+        stride: width as usize * 3,
+        channels: [0, 1, 2],
+    };
+    pixels_to_tensor_into(&pixels, output, roi, output_size, keep_aspect_ratio, output_range, flip_horizontal)
+}
+
+/// Borrowed 8-bit, 3-channel pixel data with an arbitrary row stride.
+pub(crate) struct PixelBuffer<'a> {
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    /// Number of bytes between the starts of two consecutive rows.
+    pub stride: usize,
+    /// Byte offsets of the red, green and blue channels within a pixel.
+    pub channels: [usize; 3],
+}
+
+/// Convert a pixel buffer into an RGB tensor; see `rgb_image_to_tensor_into`.
+/// Rows of the output are resampled in parallel.
+pub(crate) fn pixels_to_tensor_into(
+    image: &PixelBuffer, output: &mut [f32], roi: Option<Rect>, output_size: Option<(i32, i32)>,
+    keep_aspect_ratio: bool, output_range: (f64, f64), flip_horizontal: bool,
+) -> Result<(f64, f64, f64, f64), Error> {
+    let (image_width, image_height) = (image.width, image.height);
+    let roi = roi
+        .unwrap_or(Rect::new(0.5, 0.5, 1.0, 1.0, 0.0, true))
+        .scaled((image_width as f64, image_height as f64), false);
+
+    let output_size = output_size.unwrap_or((roi.width as i32, roi.height as i32));
+    let (width, height) = if keep_aspect_ratio {
+        (roi.size().0 as i32, roi.size().1 as i32)
+    } else {
+        output_size
+    };
+    if width <= 0 || height <= 0 || output_size.0 <= 0 || output_size.1 <= 0 {
+        return Err(Error::msg("roi and output_size must not be empty"));
+    }
+
+    let (out_width, out_height) = (output_size.0 as usize, output_size.1 as usize);
+    let mut tensors = ArrayViewMut3::from_shape((out_height, out_width, 3), output).map_err(|_| {
+        Error::msg(format!("output tensor must have {} values", out_width * out_height * 3))
+    })?;
+
+    // Coefficients mapping the ROI image onto the input image
+    let src_points = roi.points();
+    let dst_points = vec![(0., 0.), (width as f64, 0.), (width as f64, height as f64), (0., height as f64)];
+    let c = perspective_transform_coeff(&src_points, &dst_points)?;
+
+    let (mut pad_x, mut pad_y) = (0., 0.);
+    if keep_aspect_ratio {
+        let out_aspect = output_size.1 as f64 / output_size.0 as f64;
+        let roi_aspect = roi.height / roi.width;
+        if out_aspect > roi_aspect {
+            pad_y = (1.0 - roi_aspect / out_aspect) / 2.0;
+        } else {
+            pad_x = (1.0 - out_aspect / roi_aspect) / 2.0;
+        }
+    }
+
+    let (min_val, max_val) = output_range;
+    let scale = (max_val - min_val) / 255.0;
+    tensors.fill(min_val as f32);
+
+    tensors
+        .axis_iter_mut(Axis(0))
+        .into_par_iter()
+        .enumerate()
+        .for_each(|(oy, mut row)| {
+            let v = ((oy as f64 + 0.5) / out_height as f64 - pad_y) / (1.0 - 2.0 * pad_y);
+            if !(0.0..=1.0).contains(&v) {
+                return;
+            }
+            for (ox, mut pixel) in row.outer_iter_mut().enumerate() {
+                let u = ((ox as f64 + 0.5) / out_width as f64 - pad_x) / (1.0 - 2.0 * pad_x);
+                if !(0.0..=1.0).contains(&u) {
+                    continue;
+                }
+                let u = if flip_horizontal { 1.0 - u } else { u };
+
+                let (x, y) = (u * width as f64, v * height as f64);
+                let w = c[6] * x + c[7] * y + 1.0;
+                let src_x = (c[0] * x + c[1] * y + c[2]) / w - 0.5;
+                let src_y = (c[3] * x + c[4] * y + c[5]) / w - 0.5;
+
+                let value = sample_bilinear(image, src_x, src_y);
+                for i in 0..3 {
+                    pixel[i] = (value[i] * scale + min_val) as f32;
+                }
+            }
+        });
+
+    Ok((pad_x, pad_y, pad_x, pad_y))
+}
+
+/// Sample a pixel buffer at a sub-pixel position; pixels outside of the image are black.
+fn sample_bilinear(image: &PixelBuffer, x: f64, y: f64) -> [f64; 3] {
+    let (width, height) = (image.width as i64, image.height as i64);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let mut value = [0.0; 3];
+    for (dy, wy) in [(0, 1.0 - fy), (1, fy)] {
+        for (dx, wx) in [(0, 1.0 - fx), (1, fx)] {
+            let (px, py) = (x0 as i64 + dx, y0 as i64 + dy);
+            let weight = wx * wy;
+            if weight == 0.0 || px < 0 || py < 0 || px >= width || py >= height {
+                continue;
+            }
+            let index = py as usize * image.stride + px as usize * 3;
+            for (channel, offset) in value.iter_mut().zip(image.channels) {
+                *channel += image.data[index + offset] as f64 * weight;
+            }
+        }
+    }
+    value
+}
+
+/// Return the coefficients `(a, b, c, d, e, f, g, h)` of the perspective transform
+/// mapping `dst_points` onto `src_points`:
+/// `x' = (a x + b y + c) / (g x + h y + 1)`, `y' = (d x + e y + f) / (g x + h y + 1)`.
+fn perspective_transform_coeff(src_points: &[(f64, f64)], dst_points: &[(f64, f64)]) -> Result<[f64; 8], Error> {
     if src_points.len() != 4 || dst_points.len() != 4 {
         return Err(Error::msg("src_points and dst_points must contain exactly 4 points each."));
     }
@@ -329,7 +562,7 @@ fn perspective_transform_coeff(src_points: &Vec<(f32, f32)>, dst_points: &Vec<(f
         b.push(*y2);
     }
 
-    let flat_matrix: Vec<f32> = matrix.into_iter().flatten().collect();
+    let flat_matrix: Vec<f64> = matrix.into_iter().flatten().collect();
     // Convert the flattened matrix to a 2D array (8x8) and B to a 1D array (8)
     let a = nalgebra::DMatrix::from_row_slice(8, 8, &flat_matrix);
     let b = nalgebra::DVector::from_column_slice(&b);
@@ -339,8 +572,8 @@ fn perspective_transform_coeff(src_points: &Vec<(f32, f32)>, dst_points: &Vec<(f
     match coeffs {
         Some(solution) => {
             let mut result = [0.0; 8];
-            for i in 0..8 {
-                result[i] = solution[i];
+            for (i, value) in result.iter_mut().enumerate() {
+                *value = solution[i];
             }
             Ok(result)
         }
@@ -430,3 +663,118 @@ pub fn project_landmarks(
         .collect();
     Ok(landmark)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_rgb_image_to_tensor() {
+        let image = RgbImage::from_fn(4, 3, |x, y| Rgb([(x * 60) as u8, (y * 100) as u8, 255]));
+
+        let tensor = rgb_image_to_tensor(&image, None, None, false, (0., 1.), false).unwrap();
+        assert_eq!(tensor.tensor_data.shape(), &[3, 4, 3]);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            for i in 0..3 {
+                let value = tensor.tensor_data[[y as usize, x as usize, i]];
+                assert!((value - pixel[i] as f32 / 255.).abs() < 1e-6);
+            }
+        }
+
+        // letterboxed into a square and flipped
+        let tensor = rgb_image_to_tensor(&image, None, Some((8, 8)), true, (-1., 1.), true).unwrap();
+        assert_eq!(tensor.tensor_data.shape(), &[8, 8, 3]);
+        assert_eq!(tensor.padding, (0., 0.125, 0., 0.125));
+        assert_eq!(tensor.tensor_data[[0, 4, 2]], -1.);
+        assert!((tensor.tensor_data[[4, 4, 2]] - 1.).abs() < 1e-6);
+        assert!(tensor.tensor_data[[4, 1, 0]] > tensor.tensor_data[[4, 6, 0]]);
+    }
+
+    #[test]
+    fn test_rgb_image_to_tensor_into() {
+        let image = RgbImage::from_fn(7, 5, |x, y| Rgb([(x * 30) as u8, (y * 50) as u8, (x * y * 7) as u8]));
+        let roi = Some(Rect::new(0.4, 0.6, 0.7, 0.5, 0.4, true));
+
+        for (output_size, keep_aspect_ratio, flip_horizontal) in [((6, 4), false, true), ((3, 8), true, false)] {
+            let expected =
+                rgb_image_to_tensor(&image, roi, Some(output_size), keep_aspect_ratio, (-1., 1.), flip_horizontal)
+                    .unwrap();
+            assert_eq!(expected.tensor_data.shape(), &[output_size.1 as usize, output_size.0 as usize, 3]);
+
+            let mut output = vec![0f32; (output_size.0 * output_size.1 * 3) as usize];
+            let padding = rgb_image_to_tensor_into(
+                &image,
+                &mut output,
+                roi,
+                Some(output_size),
+                keep_aspect_ratio,
+                (-1., 1.),
+                flip_horizontal,
+            )
+            .unwrap();
+            assert_eq!(padding, expected.padding);
+            assert_eq!(output.as_slice(), expected.tensor_data.as_slice().unwrap());
+        }
+
+        let mut output = vec![0f32; 6 * 4 * 3 - 1];
+        assert!(rgb_image_to_tensor_into(&image, &mut output, roi, Some((6, 4)), false, (0., 1.), false).is_err());
+    }
+
+    /// Per-pixel conversion of the original `image_to_tensor` implementation.
+    #[cfg(feature = "opencv")]
+    fn reference_mat_to_tensor(image: &Mat, output_range: (f64, f64)) -> Array3<f32> {
+        use opencv::core::Vec3b;
+
+        let (min_val, max_val) = output_range;
+        let img_shape = image.size().unwrap();
+        let mut tensors = Array3::<f32>::zeros((img_shape.height as usize, img_shape.width as usize, 3usize));
+
+        for i in 0..3 {
+            for y in 0..img_shape.height as usize {
+                for x in 0..img_shape.width as usize {
+                    let pixel_value = image.at_2d::<Vec3b>(y as i32, x as i32).unwrap()[i];
+                    tensors[[y, x, i]] = (pixel_value as f64 * (max_val - min_val) / 255.0 + min_val) as f32;
+                }
+            }
+        }
+        tensors
+    }
+
+    #[cfg(feature = "opencv")]
+    #[test]
+    fn test_image_to_tensor_matches_reference() {
+        use opencv::core::Vec3b;
+
+        let (width, height) = (7, 5);
+        let mut image = Mat::new_rows_cols_with_default(height, width, CV_8UC3, Scalar::all(0.0)).unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = [(x * 30) as u8, (y * 50) as u8, (x * y * 7) as u8];
+                *image.at_2d_mut::<Vec3b>(y, x).unwrap() = Vec3b::from(pixel);
+            }
+        }
+
+        let cases = [
+            (None, None, false, false),
+            (Some(Rect::new(0.4, 0.6, 0.7, 0.5, 0.4, true)), Some((6, 4)), false, true),
+            (None, Some((4, 6)), true, false),
+            (None, Some((8, 4)), true, true),
+        ];
+        for (roi, output_size, keep_aspect_ratio, flip_horizontal) in cases {
+            let (roi_image, padding) =
+                warp_roi_image(&image, roi, output_size, keep_aspect_ratio, flip_horizontal).unwrap();
+            let expected = reference_mat_to_tensor(&roi_image, (-1., 1.));
+
+            let tensor =
+                image_to_tensor(&image, roi, output_size, keep_aspect_ratio, (-1., 1.), flip_horizontal).unwrap();
+            let (out_width, out_height) = output_size.unwrap_or((width, height));
+            assert_eq!(tensor.tensor_data.shape(), &[out_height as usize, out_width as usize, 3]);
+            assert_eq!(tensor.padding, padding);
+            assert_eq!(tensor.original_size, (width, height));
+            for (value, expected) in tensor.tensor_data.iter().zip(expected.iter()) {
+                assert!((value - expected).abs() < 1e-6);
+            }
+        }
+    }
+}
@@ -1,23 +1,105 @@
 use anyhow::Error;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader};
 use ndarray::Array2;
-use opencv::core::{Mat};
-use opencv::imgcodecs::{imdecode, IMREAD_COLOR};
+#[cfg(feature = "opencv")]
+use opencv::core::{flip, rotate, Mat, ROTATE_180, ROTATE_90_CLOCKWISE, ROTATE_90_COUNTERCLOCKWISE};
+#[cfg(feature = "opencv")]
+use opencv::imgcodecs::{imdecode, IMREAD_COLOR, IMREAD_IGNORE_ORIENTATION};
+#[cfg(feature = "opencv")]
 use opencv::imgproc::{cvt_color, COLOR_BGR2RGB};
 use ndarray_linalg::{Scalar};
+use std::io::Cursor;
 
+/// convert_image_to_mat decodes an encoded image into an RGB matrix,
+/// applying its EXIF orientation
+///
+/// # Arguments
+/// * `im_bytes` - &[u8]
+///
+/// # Returns
+/// * `Mat`
+#[cfg(feature = "opencv")]
 pub fn convert_image_to_mat(im_bytes: &[u8]) -> Result<Mat, Error> {
     // Convert bytes to Mat
     let img_as_mat = Mat::from_slice(im_bytes)?;
 
-    // Decode the image
-    let brg_img = imdecode(&img_as_mat, IMREAD_COLOR)?;
+    // Decode the image; the orientation is applied below, the same way as in `decode_image`
+    let brg_img = imdecode(&img_as_mat, IMREAD_COLOR | IMREAD_IGNORE_ORIENTATION)?;
 
     let mut rgb_img = Mat::default();
     cvt_color(&brg_img, &mut rgb_img, COLOR_BGR2RGB, 0)?;
 
     // let mut res = Mat::default();
     // cvt_color(&rgb_img, &mut res, COLOR_BGR2RGB, 0)?;
-    Ok(rgb_img)
+    apply_orientation(rgb_img, image_orientation(im_bytes))
+}
+
+/// decode_image decodes an encoded image (JPEG, PNG, ...) into an RGB image,
+/// applying its EXIF orientation
+///
+/// # Arguments
+/// * `im_bytes` - &[u8]
+///
+/// # Returns
+/// * `DynamicImage`
+pub fn decode_image(im_bytes: &[u8]) -> Result<DynamicImage, Error> {
+    let mut decoder = ImageReader::new(Cursor::new(im_bytes)).with_guessed_format()?.into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(DynamicImage::ImageRgb8(image.to_rgb8()))
+}
+
+/// image_orientation reads the EXIF orientation of an encoded image
+///
+/// # Arguments
+/// * `im_bytes` - &[u8]
+///
+/// # Returns
+/// * `Orientation` - `NoTransforms` if the image has no (readable) orientation
+pub fn image_orientation(im_bytes: &[u8]) -> Orientation {
+    ImageReader::new(Cursor::new(im_bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.orientation().ok())
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+/// apply_orientation rotates and flips a matrix like `DynamicImage::apply_orientation`
+///
+/// # Arguments
+/// * `image` - `Mat`
+/// * `orientation` - `Orientation`
+///
+/// # Returns
+/// * `Mat`
+#[cfg(feature = "opencv")]
+pub fn apply_orientation(image: Mat, orientation: Orientation) -> Result<Mat, Error> {
+    let (rotation, flip_horizontal) = match orientation {
+        Orientation::NoTransforms => (None, false),
+        Orientation::Rotate90 => (Some(ROTATE_90_CLOCKWISE), false),
+        Orientation::Rotate180 => (Some(ROTATE_180), false),
+        Orientation::Rotate270 => (Some(ROTATE_90_COUNTERCLOCKWISE), false),
+        Orientation::FlipHorizontal => (None, true),
+        Orientation::FlipVertical => (Some(ROTATE_180), true),
+        Orientation::Rotate90FlipH => (Some(ROTATE_90_CLOCKWISE), true),
+        Orientation::Rotate270FlipH => (Some(ROTATE_90_COUNTERCLOCKWISE), true),
+    };
+
+    let mut image = image;
+    if let Some(rotation) = rotation {
+        let mut rotated = Mat::default();
+        rotate(&image, &mut rotated, rotation)?;
+        image = rotated;
+    }
+    if flip_horizontal {
+        let mut flipped = Mat::default();
+        flip(&image, &mut flipped, 1)?;
+        image = flipped;
+    }
+    Ok(image)
 }
 
 /// l2_norm calculates the l2 normalized
@@ -36,15 +118,52 @@ pub fn l2_norm(arr: &Array2<f32>) -> Array2<f32> {
 /// similarity_score calculates the cosine similarity
 ///
 /// # Arguments
-/// * `a` - &Vec<f32>
-/// * `b` - &Vec<f32>
+/// * `a` - &[f32]
+/// * `b` - &[f32]
 ///
 /// # Returns
 /// * `f32`
-pub fn similarity_score(a: &Vec<f32>, b: &Vec<f32>)  -> f32 {
+pub fn similarity_score(a: &[f32], b: &[f32])  -> f32 {
     let dot_product: f32 = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum();
     let norm_a = a.iter().map(|a| a.powi(2)).sum::<f32>().sqrt();
     let norm_b = b.iter().map(|b| b.powi(2)).sum::<f32>().sqrt();
     let cosine = dot_product / (norm_a * norm_b);
     cosine
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, feature = "opencv"))]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use opencv::core::{MatTraitConst, Vec3b};
+
+    #[test]
+    fn test_apply_orientation() {
+        let image = RgbImage::from_fn(3, 2, |x, y| Rgb([(x * 50) as u8, (y * 100) as u8, 7]));
+        let mat = Mat::from_slice(image.as_raw()).unwrap().reshape(3, 2).unwrap().try_clone().unwrap();
+
+        let orientations = [
+            Orientation::NoTransforms,
+            Orientation::Rotate90,
+            Orientation::Rotate180,
+            Orientation::Rotate270,
+            Orientation::FlipHorizontal,
+            Orientation::FlipVertical,
+            Orientation::Rotate90FlipH,
+            Orientation::Rotate270FlipH,
+        ];
+        for orientation in orientations {
+            let mut expected = DynamicImage::ImageRgb8(image.clone());
+            expected.apply_orientation(orientation);
+            let expected = expected.to_rgb8();
+
+            let oriented = apply_orientation(mat.try_clone().unwrap(), orientation).unwrap();
+            let size = oriented.size().unwrap();
+            assert_eq!((size.width as u32, size.height as u32), expected.dimensions());
+            for (x, y, pixel) in expected.enumerate_pixels() {
+                let value = oriented.at_2d::<Vec3b>(y as i32, x as i32).unwrap();
+                assert_eq!([value[0], value[1], value[2]], pixel.0, "{:?}", orientation);
+            }
+        }
+    }
+}
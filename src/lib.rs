@@ -10,9 +10,8 @@ mod tests {
         eye_landmarks_to_render_data, iris_landmarks_to_render_data, iris_roi_from_face_landmarks, IrisLandmark,
     };
     use crate::face_detection_lite::render::{detections_to_render_data, landmarks_to_render_data, render_to_image, Colors, Annotation};
-    use crate::face_detection_lite::utils::convert_image_to_mat;
-    use image::ImageReader;
-    use opencv::core::MatTraitConst;
+    use crate::face_detection_lite::input::ImageInput;
+    use crate::face_detection_lite::utils::decode_image;
     use crate::face_detection_lite::types::Landmark;
 
     #[test]
@@ -21,19 +20,19 @@ mod tests {
 
         // test data
         let im_bytes: &[u8] = include_bytes!("../test_data/man.jpg");
-        let image = convert_image_to_mat(im_bytes).unwrap();
-        let img_shape = image.size().unwrap();
+        let image = decode_image(im_bytes).unwrap();
+        let img_shape = image.image_size().unwrap();
 
         // Face detection
         let faces = face_detection.infer(&image, None).unwrap();
-        let face_roi = face_detection_to_roi(faces[0].clone(), (img_shape.width, img_shape.height), None).unwrap();
+        let face_roi = face_detection_to_roi(faces[0].clone(), img_shape, None).unwrap();
 
         // Face landmark
         let face_landmark = FaceLandmark::new(None).unwrap();
         let lmks = face_landmark.infer(&image, Some(face_roi)).unwrap();
 
         // Face irises
-        let (left_eye_roi, right_eye_roi) = iris_roi_from_face_landmarks(lmks.clone(), (img_shape.width, img_shape.height)).unwrap();
+        let (left_eye_roi, right_eye_roi) = iris_roi_from_face_landmarks(lmks.clone(), img_shape).unwrap();
         let iris_landmark = IrisLandmark::new(None).unwrap();
 
         let right_iris_lmk = iris_landmark.infer(&image, Some(right_eye_roi), Some(true)).unwrap();
@@ -50,17 +49,12 @@ mod tests {
             None,
         );
 
-        let img = ImageReader::open("./test_data/man.jpg")
-            .unwrap()
-            .decode()
-            .unwrap();
-
-        let res = render_to_image(&render_data, &img, None);
+        let res = render_to_image(&render_data, &image, None);
         res.save("./assets/man_bbox.png").unwrap();
 
         // Draw face landmarks
         let annotations = face_landmarks_to_render_data(lmks.clone(), Colors::RED, Colors::RED, Some(2.0), None);
-        let res = render_to_image(&annotations, &img, None);
+        let res = render_to_image(&annotations, &image, None);
         res.save("./assets/man_landmark.png").unwrap();
 
 
@@ -79,7 +73,7 @@ mod tests {
         iris_lmks.extend(left_iris_lmk);
 
 
-        let res = render_to_image(&iris_lmks, &img, None);
+        let res = render_to_image(&iris_lmks, &image, None);
         res.save("./assets/man_iris.png").unwrap();
     }
 }